@@ -1,820 +1,2578 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
-use std::path::Path;
-
-// Token types
-#[derive(Debug, Clone, PartialEq)]
-enum TokenType {
-    Identifier,
-    Keyword,
-    Operator,
-    Literal,
-    Separator,
-    Comment,
-    Whitespace,
-}
-
-// Token structure
-#[derive(Debug, Clone)]
-struct Token {
-    token_type: TokenType,
-    value: String,
-    line: usize,
-    column: usize,
-}
-
-// Abstract Syntax Tree Node
-#[derive(Debug)]
-enum ASTNode {
-    Program(Vec<ASTNode>),
-    FunctionDeclaration {
-        name: String,
-        parameters: Vec<ASTNode>,
-        return_type: Box<ASTNode>,
-        body: Box<ASTNode>,
-    },
-    VariableDeclaration {
-        name: String,
-        var_type: Box<ASTNode>,
-        initializer: Option<Box<ASTNode>>,
-    },
-    Type(String),
-    Block(Vec<ASTNode>),
-    Expression(Box<ASTNode>),
-    BinaryOperation {
-        left: Box<ASTNode>,
-        operator: String,
-        right: Box<ASTNode>,
-    },
-    UnaryOperation {
-        operator: String,
-        operand: Box<ASTNode>,
-    },
-    Literal(String),
-    Identifier(String),
-}
-
-// Parser structure
-struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
-    ast: Option<ASTNode>,
-}
-
-impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
-        Parser {
-            tokens,
-            current: 0,
-            ast: None,
-        }
-    }
-
-    fn parse(&mut self) -> Result<ASTNode, String> {
-        let mut program_nodes = Vec::new();
-
-        while !self.is_at_end() {
-            match self.parse_declaration() {
-                Ok(node) => program_nodes.push(node),
-                Err(e) => return Err(e),
-            }
-        }
-
-        self.ast = Some(ASTNode::Program(program_nodes));
-        Ok(self.ast.clone().unwrap())
-    }
-
-    fn parse_declaration(&mut self) -> Result<ASTNode, String> {
-        if self.match_token(TokenType::Keyword, "fn") {
-            self.parse_function_declaration()
-        } else if self.match_token(TokenType::Keyword, "let") {
-            self.parse_variable_declaration()
-        } else {
-            Err("Expected declaration".to_string())
-        }
-    }
-
-    fn parse_function_declaration(&mut self) -> Result<ASTNode, String> {
-        let name = self.expect_identifier()?;
-        self.expect_token(TokenType::Separator, "(")?;
-        let parameters = self.parse_parameters()?;
-        self.expect_token(TokenType::Separator, ")")?;
-        self.expect_token(TokenType::Separator, "->")?;
-        let return_type = Box::new(self.parse_type()?);
-        let body = Box::new(self.parse_block()?);
-
-        Ok(ASTNode::FunctionDeclaration {
-            name,
-            parameters,
-            return_type,
-            body,
-        })
-    }
-
-    fn parse_parameters(&mut self) -> Result<Vec<ASTNode>, String> {
-        let mut parameters = Vec::new();
-
-        if !self.check(TokenType::Separator, ")") {
-            loop {
-                let param_name = self.expect_identifier()?;
-                self.expect_token(TokenType::Separator, ":")?;
-                let param_type = self.parse_type()?;
-                parameters.push(ASTNode::VariableDeclaration {
-                    name: param_name,
-                    var_type: Box::new(param_type),
-                    initializer: None,
-                });
-
-                if !self.match_token(TokenType::Separator, ",") {
-                    break;
-                }
-            }
-        }
-
-        Ok(parameters)
-    }
-
-    fn parse_type(&mut self) -> Result<ASTNode, String> {
-        let type_name = self.expect_identifier()?;
-        Ok(ASTNode::Type(type_name))
-    }
-
-    fn parse_block(&mut self) -> Result<ASTNode, String> {
-        self.expect_token(TokenType::Separator, "{")?;
-        let mut statements = Vec::new();
-
-        while !self.check(TokenType::Separator, "}") && !self.is_at_end() {
-            statements.push(self.parse_statement()?);
-        }
-
-        self.expect_token(TokenType::Separator, "}")?;
-        Ok(ASTNode::Block(statements))
-    }
-
-    fn parse_statement(&mut self) -> Result<ASTNode, String> {
-        if self.match_token(TokenType::Keyword, "let") {
-            self.parse_variable_declaration()
-        } else {
-            self.parse_expression_statement()
-        }
-    }
-
-    fn parse_variable_declaration(&mut self) -> Result<ASTNode, String> {
-        let name = self.expect_identifier()?;
-        self.expect_token(TokenType::Separator, ":")?;
-        let var_type = Box::new(self.parse_type()?);
-
-        let initializer = if self.match_token(TokenType::Operator, "=") {
-            Some(Box::new(self.parse_expression()?))
-        } else {
-            None
-        };
-
-        self.expect_token(TokenType::Separator, ";")?;
-
-        Ok(ASTNode::VariableDeclaration {
-            name,
-            var_type,
-            initializer,
-        })
-    }
-
-    fn parse_expression_statement(&mut self) -> Result<ASTNode, String> {
-        let expr = self.parse_expression()?;
-        self.expect_token(TokenType::Separator, ";")?;
-        Ok(ASTNode::Expression(Box::new(expr)))
-    }
-
-    fn parse_expression(&mut self) -> Result<ASTNode, String> {
-        self.parse_assignment()
-    }
-
-    fn parse_assignment(&mut self) -> Result<ASTNode, String> {
-        let expr = self.parse_equality()?;
-
-        if self.match_token(TokenType::Operator, "=") {
-            let value = self.parse_assignment()?;
-            match expr {
-                ASTNode::Identifier(name) => {
-                    Ok(ASTNode::BinaryOperation {
-                        left: Box::new(ASTNode::Identifier(name)),
-                        operator: "=".to_string(),
-                        right: Box::new(value),
-                    })
-                }
-                _ => Err("Invalid assignment target".to_string()),
-            }
-        } else {
-            Ok(expr)
-        }
-    }
-
-    fn parse_equality(&mut self) -> Result<ASTNode, String> {
-        let mut expr = self.parse_comparison()?;
-
-        while self.match_any(&[
-            (TokenType::Operator, "=="),
-            (TokenType::Operator, "!="),
-        ]) {
-            let operator = self.previous().value.clone();
-            let right = self.parse_comparison()?;
-            expr = ASTNode::BinaryOperation {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
-        }
-
-        Ok(expr)
-    }
-
-    fn parse_comparison(&mut self) -> Result<ASTNode, String> {
-        let mut expr = self.parse_term()?;
-
-        while self.match_any(&[
-            (TokenType::Operator, ">"),
-            (TokenType::Operator, ">="),
-            (TokenType::Operator, "<"),
-            (TokenType::Operator, "<="),
-        ]) {
-            let operator = self.previous().value.clone();
-            let right = self.parse_term()?;
-            expr = ASTNode::BinaryOperation {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
-        }
-
-        Ok(expr)
-    }
-
-    fn parse_term(&mut self) -> Result<ASTNode, String> {
-        let mut expr = self.parse_factor()?;
-
-        while self.match_any(&[
-            (TokenType::Operator, "+"),
-            (TokenType::Operator, "-"),
-        ]) {
-            let operator = self.previous().value.clone();
-            let right = self.parse_factor()?;
-            expr = ASTNode::BinaryOperation {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
-        }
-
-        Ok(expr)
-    }
-
-    fn parse_factor(&mut self) -> Result<ASTNode, String> {
-        let mut expr = self.parse_unary()?;
-
-        while self.match_any(&[
-            (TokenType::Operator, "*"),
-            (TokenType::Operator, "/"),
-        ]) {
-            let operator = self.previous().value.clone();
-            let right = self.parse_unary()?;
-            expr = ASTNode::BinaryOperation {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
-        }
-
-        Ok(expr)
-    }
-
-    fn parse_unary(&mut self) -> Result<ASTNode, String> {
-        if self.match_any(&[
-            (TokenType::Operator, "!"),
-            (TokenType::Operator, "-"),
-        ]) {
-            let operator = self.previous().value.clone();
-            let right = self.parse_unary()?;
-            Ok(ASTNode::UnaryOperation {
-                operator,
-                operand: Box::new(right),
-            })
-        } else {
-            self.parse_primary()
-        }
-    }
-
-    fn parse_primary(&mut self) -> Result<ASTNode, String> {
-        if self.match_token(TokenType::Literal, "") {
-            Ok(ASTNode::Literal(self.previous().value.clone()))
-        } else if self.match_token(TokenType::Identifier, "") {
-            Ok(ASTNode::Identifier(self.previous().value.clone()))
-        } else if self.match_token(TokenType::Separator, "(") {
-            let expr = self.parse_expression()?;
-            self.expect_token(TokenType::Separator, ")")?;
-            Ok(expr)
-        } else {
-            Err("Expected expression".to_string())
-        }
-    }
-
-    fn match_token(&mut self, token_type: TokenType, value: &str) -> bool {
-        if self.check(token_type.clone(), value) {
-            self.advance();
-            true
-        } else {
-            false
-        }
-    }
-
-    fn match_any(&mut self, tokens: &[(TokenType, &str)]) -> bool {
-        for (token_type, value) in tokens {
-            if self.check(token_type.clone(), value) {
-                self.advance();
-                return true;
-            }
-        }
-        false
-    }
-
-    fn check(&self, token_type: TokenType, value: &str) -> bool {
-        if self.is_at_end() {
-            false
-        } else {
-            let token = &self.tokens[self.current];
-            token.token_type == token_type && (value.is_empty() || token.value == value)
-        }
-    }
-
-    fn advance(&mut self) -> &Token {
-        if !self.is_at_end() {
-            self.current += 1;
-        }
-        self.previous()
-    }
-
-    fn is_at_end(&self) -> bool {
-        self.current >= self.tokens.len()
-    }
-
-    fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
-    }
-
-    fn expect_token(&mut self, token_type: TokenType, value: &str) -> Result<(), String> {
-        if self.check(token_type.clone(), value) {
-            self.advance();
-            Ok(())
-        } else {
-            Err(format!("Expected token: {:?} '{}'", token_type, value))
-        }
-    }
-
-    fn expect_identifier(&mut self) -> Result<String, String> {
-        if self.match_token(TokenType::Identifier, "") {
-            Ok(self.previous().value.clone())
-        } else {
-            Err("Expected identifier".to_string())
-        }
-    }
-}
-
-// Lexer structure
-struct Lexer {
-    input: String,
-    tokens: Vec<Token>,
-    start: usize,
-    current: usize,
-    line: usize,
-}
-
-impl Lexer {
-    fn new(input: String) -> Self {
-        Lexer {
-            input,
-            tokens: Vec::new(),
-            start: 0,
-            current: 0,
-            line: 1,
-        }
-    }
-
-    fn tokenize(&mut self) -> Result<Vec<Token>, String> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token()?;
-        }
-
-        self.tokens.push(Token {
-            token_type: TokenType::Separator,
-            value: "EOF".to_string(),
-            line: self.line,
-            column: self.current,
-        });
-
-        Ok(self.tokens.clone())
-    }
-
-    fn scan_token(&mut self) -> Result<(), String> {
-        let c = self.advance();
-        match c {
-            '(' | ')' | '{' | '}' | ',' | ';' | ':' => self.add_token(TokenType::Separator),
-            '+' | '-' | '*' | '/' => self.add_token(TokenType::Operator),
-            '=' => {
-                if self.match_char('=') {
-                    self.add_token(TokenType::Operator);
-                } else {
-                    self.add_token(TokenType::Operator);
-                }
-            }
-            '!' => {
-                if self.match_char('=') {
-                    self.add_token(TokenType::Operator);
-                } else {
-                    self.add_token(TokenType::Operator);
-                }
-            }
-            '<' => {
-                if self.match_char('=') {
-                    self.add_token(TokenType::Operator);
-                } else {
-                    self.add_token(TokenType::Operator);
-                }
-            }
-            '>' => {
-                if self.match_char('=') {
-                    self.add_token(TokenType::Operator);
-                } else {
-                    self.add_token(TokenType::Operator);
-                }
-            }
-            '"' => self.string()?,
-            '0'..='9' => self.number(),
-            'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
-            ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
-            '/' => {
-                if self.match_char('/') {
-                    while self.peek() != '\n' && !self.is_at_end() {
-                        self.advance();
-                    }
-                } else {
-                    self.add_token(TokenType::Operator);
-                }
-            }
-            _ => return Err(format!("Unexpected character: {}", c)),
-        }
-        Ok(())
-    }
-
-    fn advance(&mut self) -> char {
-        let c = self.input.chars().nth(self.current).unwrap();
-        self.current += 1;
-        c
-    }
-
-    fn match_char(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            return false;
-        }
-        if self.input.chars().nth(self.current).unwrap() != expected {
-            return false;
-        }
-        self.current += 1;
-        true
-    }
-
-    fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.input.chars().nth(self.current).unwrap()
-        }
-    }
-
-    fn is_at_end(&self) -> bool {
-        self.current >= self.input.len()
-    }
-
-    fn add_token(&mut self, token_type: TokenType) {
-        let text = &self.input[self.start..self.current];
-        self.tokens.push(Token {
-            token_type,
-            value: text.to_string(),
-            line: self.line,
-            column: self.start,
-        });
-    }
-
-    fn string(&mut self) -> Result<(), String> {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
-            }
-            self.advance();
-        }
-
-        if self.is_at_end() {
-            return Err("Unterminated string".to_string());
-        }
-
-        self.advance();
-        let value = &self.input[self.start + 1..self.current - 1];
-        self.add_token(TokenType::Literal);
-        Ok(())
-    }
-
-    fn number(&mut self) {
-        while self.peek().is_digit(10) {
-            self.advance();
-        }
-
-        if self.peek() == '.' && self.input.chars().nth(self.current + 1).unwrap().is_digit(10) {
-            self.advance();
-
-            while self.peek().is_digit(10) {
-                self.advance();
-            }
-        }
-
-        self.add_token(TokenType::Literal);
-    }
-
-    fn identifier(&mut self) {
-        while self.peek().is_alphanumeric() || self.peek() == '_' {
-            self.advance();
-        }
-
-        let text = &self.input[self.start..self.current];
-        let token_type = match text {
-            "fn" | "let" | "if" | "else" | "while" | "return" => TokenType::Keyword,
-            _ => TokenType::Identifier,
-        };
-
-        self.add_token(token_type);
-    }
-}
-
-fn main() -> io::Result<()> {
-    println!("D++ C Parser Initialization");
-    println!("---------------------------");
-
-    // Read input file
-    let file_path = "input.dpp";
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut input = String::new();
-
-    for line in reader.lines() {
-        input.push_str(&line?);
-        input.push('\n');
-    }
-
-    // Initialize lexer
-    let mut lexer = Lexer::new(input);
-    let tokens = match lexer.tokenize() {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("Lexer error: {}", e);
-            return Ok(());
-        }
-    };
-
-    println!("Tokenization complete. Found {} tokens.", tokens.len());
-
-    // Initialize parser
-    let mut parser = Parser::new(tokens);
-    let ast = match parser.parse() {
-        Ok(a) => a,
-        Err(e) => {
-            eprintln!("Parser error: {}", e);
-            return Ok(());
-        }
-    };
-
-    println!("Parsing complete. AST generated.");
-
-    // Print AST (for demonstration purposes)
-    println!("Abstract Syntax Tree:");
-    println!("{:#?}", ast);
-
-    println!("D++ C Parser initialization complete.");
-    Ok(())
-}
-
-// Helper function to read keywords from a file
-fn read_keywords(file_path: &str) -> io::Result<Vec<String>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut keywords = Vec::new();
-
-    for line in reader.lines() {
-        keywords.push(line?);
-    }
-
-    Ok(keywords)
-}
-
-// Helper function to generate symbol table
-fn generate_symbol_table(ast: &ASTNode) -> HashMap<String, String> {
-    let mut symbol_table = HashMap::new();
-
-    fn traverse_ast(node: &ASTNode, table: &mut HashMap<String, String>) {
-        match node {
-            ASTNode::VariableDeclaration { name, var_type, .. } => {
-                if let ASTNode::Type(type_name) = **var_type {
-                    table.insert(name.clone(), type_name);
-                }
-            }
-            ASTNode::FunctionDeclaration { name, parameters, return_type, .. } => {
-                let mut param_types = Vec::new();
-                for param in parameters {
-                    if let ASTNode::VariableDeclaration { var_type, .. } = param {
-                        if let ASTNode::Type(type_name) = **var_type {
-                            param_types.push(type_name);
-                        }
-                    }
-                }
-                let ret_type = if let ASTNode::Type(type_name) = **return_type {
-                    type_name
-                } else {
-                    "void".to_string()
-                };
-                table.insert(name.clone(), format!("fn({}) -> {}", param_types.join(", "), ret_type));
-            }
-            ASTNode::Program(nodes) | ASTNode::Block(nodes) => {
-                for node in nodes {
-                    traverse_ast(node, table);
-                }
-            }
-            _ => {}
-        }
-    }
-
-    traverse_ast(ast, &mut symbol_table);
-    symbol_table
-}
-
-// Helper function to perform semantic analysis
-fn semantic_analysis(ast: &ASTNode, symbol_table: &HashMap<String, String>) -> Result<(), String> {
-    fn check_node(node: &ASTNode, table: &HashMap<String, String>) -> Result<(), String> {
-        match node {
-            ASTNode::BinaryOperation { left, operator, right } => {
-                check_node(left, table)?;
-                check_node(right, table)?;
-                // Add type checking for binary operations
-            }
-            ASTNode::UnaryOperation { operator, operand } => {
-                check_node(operand, table)?;
-                // Add type checking for unary operations
-            }
-            ASTNode::Identifier(name) => {
-                if !table.contains_key(name) {
-                    return Err(format!("Undefined variable: {}", name));
-                }
-            }
-            ASTNode::FunctionDeclaration { name, parameters, body, .. } => {
-                // Check function body
-                check_node(body, table)?;
-            }
-            ASTNode::Program(nodes) | ASTNode::Block(nodes) => {
-                for node in nodes {
-                    check_node(node, table)?;
-                }
-            }
-            _ => {}
-        }
-        Ok(())
-    }
-
-    check_node(ast, symbol_table)
-}
-
-// Helper function to optimize AST
-fn optimize_ast(ast: &mut ASTNode) {
-    fn optimize_node(node: &mut ASTNode) {
-        match node {
-            ASTNode::BinaryOperation { left, operator, right } => {
-                optimize_node(left);
-                optimize_node(right);
-                // Perform constant folding and other optimizations
-            }
-            ASTNode::UnaryOperation { operand, .. } => {
-                optimize_node(operand);
-            }
-            ASTNode::Program(nodes) | ASTNode::Block(nodes) => {
-                for node in nodes {
-                    optimize_node(node);
-                }
-            }
-            _ => {}
-        }
-    }
-
-    optimize_node(ast);
-}
-
-// Helper function to generate intermediate representation (IR)
-fn generate_ir(ast: &ASTNode) -> Vec<String> {
-    let mut ir = Vec::new();
-
-    fn generate_node_ir(node: &ASTNode, ir: &mut Vec<String>) {
-        match node {
-            ASTNode::FunctionDeclaration { name, parameters, body, .. } => {
-                ir.push(format!("function {}:", name));
-                for param in parameters {
-                    if let ASTNode::VariableDeclaration { name, .. } = param {
-                        ir.push(format!("  param {}", name));
-                    }
-                }
-                generate_node_ir(body, ir);
-                ir.push("end_function".to_string());
-            }
-            ASTNode::Block(statements) => {
-                for stmt in statements {
-                    generate_node_ir(stmt, ir);
-                }
-            }
-            ASTNode::VariableDeclaration { name, initializer, .. } => {
-                if let Some(init) = initializer {
-                    generate_node_ir(init, ir);
-                    ir.push(format!("store {}", name));
-                }
-            }
-            ASTNode::BinaryOperation { left, operator, right } => {
-                generate_node_ir(left, ir);
-                generate_node_ir(right, ir);
-                ir.push(format!("{} {}", operator, operator));
-            }
-            ASTNode::UnaryOperation { operator, operand } => {
-                generate_node_ir(operand, ir);
-                ir.push(format!("{}", operator));
-            }
-            ASTNode::Literal(value) => {
-                ir.push(format!("push {}", value));
-            }
-            ASTNode::Identifier(name) => {
-                ir.push(format!("load {}", name));
-            }
-            _ => {}
-        }
-    }
-
-    generate_node_ir(ast, &mut ir);
-    ir
-}
-
-// Helper function to generate target code (e.g., x86 assembly)
-fn generate_target_code(ir: &[String]) -> Vec<String> {
-    let mut asm = Vec::new();
-    
-    for instruction in ir {
-        let parts: Vec<&str> = instruction.split_whitespace().collect();
-        match parts[0] {
-            "function" => {
-                asm.push(format!("{}:", parts[1].trim_end_matches(':')));
-                asm.push("    push rbp".to_string());
-                asm.push("    mov rbp, rsp".to_string());
-            }
-            "end_function" => {
-                asm.push("    mov rsp, rbp".to_string());
-                asm.push("    pop rbp".to_string());
-                asm.push("    ret".to_string());
-            }
-            "param" => {
-                // Handle parameter passing
-            }
-            "push" => {
-                asm.push(format!("    push {}", parts[1]));
-            }
-            "load" => {
-                asm.push(format!("    mov rax, [{}]", parts[1]));
-                asm.push("    push rax".to_string());
-            }
-            "store" => {
-                asm.push("    pop rax".to_string());
-                asm.push(format!("    mov [{}], rax", parts[1]));
-            }
-            "+" | "-" | "*" | "/" => {
-                asm.push("    pop rbx".to_string());
-                asm.push("    pop rax".to_string());
-                match parts[0] {
-                    "+" => asm.push("    add rax, rbx".to_string()),
-                    "-" => asm.push("    sub rax, rbx".to_string()),
-                    "*" => asm.push("    imul rax, rbx".to_string()),
-                    "/" => {
-                        asm.push("    xor rdx, rdx".to_string());
-                        asm.push("    idiv rbx".to_string());
-                    }
-                    _ => {}
-                }
-                asm.push("    push rax".to_string());
-            }
-            _ => {
-                // Handle other instructions
-            }
-        }
-    }
-
-    asm
-}
\ No newline at end of file
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+// Token types
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum TokenType {
+    Identifier,
+    Keyword,
+    Operator,
+    Literal,
+    Separator,
+    Comment,
+    Whitespace,
+}
+
+// Token structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Token {
+    token_type: TokenType,
+    value: String,
+    line: usize,
+    column: usize,
+}
+
+// Source range an AST node was parsed from, used for precise diagnostics.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Span {
+    start_line: usize,
+    start_col: usize,
+    end_line: usize,
+    end_col: usize,
+}
+
+impl Span {
+    // Build a span covering everything from the first to the last token of a node.
+    fn between(start: &Token, end: &Token) -> Self {
+        Span {
+            start_line: start.line,
+            start_col: start.column,
+            end_line: end.line,
+            end_col: end.column,
+        }
+    }
+}
+
+// An AST node paired with the span of source it covers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node {
+    inner: ASTNode,
+    span: Span,
+}
+
+// Abstract Syntax Tree Node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ASTNode {
+    Program(Vec<Node>),
+    FunctionDeclaration {
+        name: String,
+        parameters: Vec<Node>,
+        return_type: Box<Node>,
+        body: Box<Node>,
+    },
+    VariableDeclaration {
+        name: String,
+        var_type: Box<Node>,
+        initializer: Option<Box<Node>>,
+    },
+    Type(String),
+    Block(Vec<Node>),
+    Expression(Box<Node>),
+    BinaryOperation {
+        left: Box<Node>,
+        operator: String,
+        right: Box<Node>,
+    },
+    UnaryOperation {
+        operator: String,
+        operand: Box<Node>,
+    },
+    If {
+        condition: Box<Node>,
+        then_branch: Box<Node>,
+        else_branch: Option<Box<Node>>,
+    },
+    While {
+        condition: Box<Node>,
+        body: Box<Node>,
+    },
+    Return(Option<Box<Node>>),
+    Call {
+        callee: Box<Node>,
+        arguments: Vec<Node>,
+    },
+    Cast {
+        operand: Box<Node>,
+        target_type: Box<Node>,
+    },
+    Literal(String),
+    Identifier(String),
+}
+
+// Parser structure
+struct Parser {
+    tokens: Vec<Token>,
+    current: usize,
+    ast: Option<Node>,
+    // Binding-power tables driving the Pratt expression parser: `infix_bp` maps
+    // an operator to its (left, right) binding powers, `prefix_bp` to the power a
+    // prefix operator binds its operand with.
+    infix_bp: HashMap<String, (u8, u8)>,
+    prefix_bp: HashMap<String, u8>,
+}
+
+impl Parser {
+    // Left binding power of the `as` cast: below the prefix operators (12) yet
+    // above the arithmetic operators so casts bind tighter than any binary op.
+    const CAST_BP: u8 = 11;
+
+    fn new(tokens: Vec<Token>) -> Self {
+        Parser {
+            tokens,
+            current: 0,
+            ast: None,
+            infix_bp: Self::infix_table(),
+            prefix_bp: Self::prefix_table(),
+        }
+    }
+
+    // Infix operators ordered from loosest to tightest binding. Assignment is
+    // right-associative (right_bp < left_bp); every other operator is
+    // left-associative (right_bp = left_bp + 1).
+    fn infix_table() -> HashMap<String, (u8, u8)> {
+        let mut table = HashMap::new();
+        table.insert("=".to_string(), (2, 1));
+        table.insert("==".to_string(), (4, 5));
+        table.insert("!=".to_string(), (4, 5));
+        table.insert("<".to_string(), (6, 7));
+        table.insert("<=".to_string(), (6, 7));
+        table.insert(">".to_string(), (6, 7));
+        table.insert(">=".to_string(), (6, 7));
+        table.insert("+".to_string(), (8, 9));
+        table.insert("-".to_string(), (8, 9));
+        table.insert("*".to_string(), (10, 11));
+        table.insert("/".to_string(), (10, 11));
+        table
+    }
+
+    // Prefix operators bind tighter than any infix operator.
+    fn prefix_table() -> HashMap<String, u8> {
+        let mut table = HashMap::new();
+        table.insert("-".to_string(), 12);
+        table.insert("!".to_string(), 12);
+        table
+    }
+
+    fn parse(&mut self) -> Result<Node, String> {
+        let start = self.current;
+        let mut program_nodes = Vec::new();
+
+        while !self.is_at_end() && !self.check(TokenType::Separator, "EOF") {
+            match self.parse_declaration() {
+                Ok(node) => program_nodes.push(node),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let program = self.spanned(ASTNode::Program(program_nodes), start);
+        self.ast = Some(program.clone());
+        Ok(program)
+    }
+
+    // Wrap a freshly parsed node kind with the span running from the token at
+    // `start` to the last token consumed.
+    fn spanned(&self, inner: ASTNode, start: usize) -> Node {
+        let start_idx = start.min(self.tokens.len().saturating_sub(1));
+        let end_idx = self.current.saturating_sub(1).max(start_idx);
+        Node {
+            inner,
+            span: Span::between(&self.tokens[start_idx], &self.tokens[end_idx]),
+        }
+    }
+
+    // Human-readable position of the token the parser is about to consume.
+    fn location(&self) -> String {
+        if self.is_at_end() {
+            "end of input".to_string()
+        } else {
+            let token = &self.tokens[self.current];
+            format!("line {}, col {}", token.line, token.column)
+        }
+    }
+
+    fn parse_declaration(&mut self) -> Result<Node, String> {
+        if self.match_token(TokenType::Keyword, "fn") {
+            self.parse_function_declaration()
+        } else if self.match_token(TokenType::Keyword, "let") {
+            self.parse_variable_declaration()
+        } else {
+            Err(format!("Expected declaration at {}", self.location()))
+        }
+    }
+
+    fn parse_function_declaration(&mut self) -> Result<Node, String> {
+        let start = self.current - 1;
+        let name = self.expect_identifier()?;
+        self.expect_token(TokenType::Separator, "(")?;
+        let parameters = self.parse_parameters()?;
+        self.expect_token(TokenType::Separator, ")")?;
+        self.expect_token(TokenType::Separator, "->")?;
+        let return_type = Box::new(self.parse_type()?);
+        let body = Box::new(self.parse_block()?);
+
+        Ok(self.spanned(
+            ASTNode::FunctionDeclaration {
+                name,
+                parameters,
+                return_type,
+                body,
+            },
+            start,
+        ))
+    }
+
+    fn parse_parameters(&mut self) -> Result<Vec<Node>, String> {
+        let mut parameters = Vec::new();
+
+        if !self.check(TokenType::Separator, ")") {
+            loop {
+                let start = self.current;
+                let param_name = self.expect_identifier()?;
+                self.expect_token(TokenType::Separator, ":")?;
+                let param_type = self.parse_type()?;
+                parameters.push(self.spanned(
+                    ASTNode::VariableDeclaration {
+                        name: param_name,
+                        var_type: Box::new(param_type),
+                        initializer: None,
+                    },
+                    start,
+                ));
+
+                if !self.match_token(TokenType::Separator, ",") {
+                    break;
+                }
+            }
+        }
+
+        Ok(parameters)
+    }
+
+    fn parse_type(&mut self) -> Result<Node, String> {
+        let start = self.current;
+        let type_name = self.expect_identifier()?;
+        Ok(self.spanned(ASTNode::Type(type_name), start))
+    }
+
+    fn parse_block(&mut self) -> Result<Node, String> {
+        let start = self.current;
+        self.expect_token(TokenType::Separator, "{")?;
+        let mut statements = Vec::new();
+
+        while !self.check(TokenType::Separator, "}") && !self.is_at_end() {
+            statements.push(self.parse_statement()?);
+        }
+
+        self.expect_token(TokenType::Separator, "}")?;
+        Ok(self.spanned(ASTNode::Block(statements), start))
+    }
+
+    fn parse_statement(&mut self) -> Result<Node, String> {
+        if self.match_token(TokenType::Keyword, "if") {
+            self.parse_if_statement()
+        } else if self.match_token(TokenType::Keyword, "while") {
+            self.parse_while_statement()
+        } else if self.match_token(TokenType::Keyword, "return") {
+            self.parse_return_statement()
+        } else if self.match_token(TokenType::Keyword, "let") {
+            self.parse_variable_declaration()
+        } else {
+            self.parse_expression_statement()
+        }
+    }
+
+    // A condition is an expression, optionally wrapped in parentheses.
+    fn parse_condition(&mut self) -> Result<Node, String> {
+        if self.match_token(TokenType::Separator, "(") {
+            let expr = self.parse_expression()?;
+            self.expect_token(TokenType::Separator, ")")?;
+            Ok(expr)
+        } else {
+            self.parse_expression()
+        }
+    }
+
+    fn parse_if_statement(&mut self) -> Result<Node, String> {
+        let start = self.current - 1;
+        let condition = Box::new(self.parse_condition()?);
+        let then_branch = Box::new(self.parse_block()?);
+
+        // `else` optionally chains into another `if` or a plain block.
+        let else_branch = if self.match_token(TokenType::Keyword, "else") {
+            if self.match_token(TokenType::Keyword, "if") {
+                Some(Box::new(self.parse_if_statement()?))
+            } else {
+                Some(Box::new(self.parse_block()?))
+            }
+        } else {
+            None
+        };
+
+        Ok(self.spanned(
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+            },
+            start,
+        ))
+    }
+
+    fn parse_while_statement(&mut self) -> Result<Node, String> {
+        let start = self.current - 1;
+        let condition = Box::new(self.parse_condition()?);
+        let body = Box::new(self.parse_block()?);
+
+        Ok(self.spanned(ASTNode::While { condition, body }, start))
+    }
+
+    fn parse_return_statement(&mut self) -> Result<Node, String> {
+        let start = self.current - 1;
+        let value = if self.check(TokenType::Separator, ";") {
+            None
+        } else {
+            Some(Box::new(self.parse_expression()?))
+        };
+        self.expect_token(TokenType::Separator, ";")?;
+
+        Ok(self.spanned(ASTNode::Return(value), start))
+    }
+
+    fn parse_variable_declaration(&mut self) -> Result<Node, String> {
+        let start = self.current - 1;
+        let name = self.expect_identifier()?;
+        self.expect_token(TokenType::Separator, ":")?;
+        let var_type = Box::new(self.parse_type()?);
+
+        let initializer = if self.match_token(TokenType::Operator, "=") {
+            Some(Box::new(self.parse_expression()?))
+        } else {
+            None
+        };
+
+        self.expect_token(TokenType::Separator, ";")?;
+
+        Ok(self.spanned(
+            ASTNode::VariableDeclaration {
+                name,
+                var_type,
+                initializer,
+            },
+            start,
+        ))
+    }
+
+    fn parse_expression_statement(&mut self) -> Result<Node, String> {
+        let start = self.current;
+        let expr = self.parse_expression()?;
+        self.expect_token(TokenType::Separator, ";")?;
+        Ok(self.spanned(ASTNode::Expression(Box::new(expr)), start))
+    }
+
+    fn parse_expression(&mut self) -> Result<Node, String> {
+        self.parse_expr(0)
+    }
+
+    // Pratt (top-down operator precedence) expression parser. Parses a
+    // prefix/primary, then folds infix operators whose left binding power is at
+    // least `min_bp`, recursing with the operator's right binding power so that
+    // associativity falls out of the table entries.
+    fn parse_expr(&mut self, min_bp: u8) -> Result<Node, String> {
+        let start = self.current;
+
+        // nud: a prefix operator or a primary expression.
+        let mut lhs = match self.peek_operator() {
+            Some(op) if self.prefix_bp.contains_key(&op) => {
+                let right_bp = self.prefix_bp[&op];
+                self.advance();
+                let operand = self.parse_expr(right_bp)?;
+                self.spanned(
+                    ASTNode::UnaryOperation {
+                        operator: op,
+                        operand: Box::new(operand),
+                    },
+                    start,
+                )
+            }
+            _ => self.parse_postfix()?,
+        };
+
+        // led: consume infix operators as long as they bind tightly enough. The
+        // `as` cast is woven in here with a binding power below any prefix
+        // operator (so `-x as u64` parses as `(-x) as u64`) but above the
+        // arithmetic operators (so `a * b as c` parses as `a * (b as c)`).
+        loop {
+            if self.check(TokenType::Keyword, "as") {
+                if Self::CAST_BP < min_bp {
+                    break;
+                }
+                self.advance();
+                let target_type = self.parse_type()?;
+                lhs = self.spanned(
+                    ASTNode::Cast {
+                        operand: Box::new(lhs),
+                        target_type: Box::new(target_type),
+                    },
+                    start,
+                );
+                continue;
+            }
+
+            let op = match self.peek_operator() {
+                Some(op) => op,
+                None => break,
+            };
+            let (left_bp, right_bp) = match self.infix_bp.get(&op) {
+                Some(&bp) => bp,
+                None => break,
+            };
+            if left_bp < min_bp {
+                break;
+            }
+
+            self.advance();
+            let rhs = self.parse_expr(right_bp)?;
+
+            if op == "=" && !matches!(lhs.inner, ASTNode::Identifier(_)) {
+                return Err(format!("Invalid assignment target at {}", self.location()));
+            }
+
+            lhs = self.spanned(
+                ASTNode::BinaryOperation {
+                    left: Box::new(lhs),
+                    operator: op,
+                    right: Box::new(rhs),
+                },
+                start,
+            );
+        }
+
+        Ok(lhs)
+    }
+
+    // The value of the operator token the parser is about to consume, if any.
+    fn peek_operator(&self) -> Option<String> {
+        if self.is_at_end() {
+            return None;
+        }
+        let token = &self.tokens[self.current];
+        if token.token_type == TokenType::Operator {
+            Some(token.value.clone())
+        } else {
+            None
+        }
+    }
+
+    // A primary expression followed by zero or more call suffixes, so that
+    // `f(x)` and even `f(x)(y)` parse into nested `Call` nodes.
+    fn parse_postfix(&mut self) -> Result<Node, String> {
+        let start = self.current;
+        let mut expr = self.parse_primary()?;
+
+        while self.match_token(TokenType::Separator, "(") {
+            let arguments = self.parse_arguments()?;
+            self.expect_token(TokenType::Separator, ")")?;
+            expr = self.spanned(
+                ASTNode::Call {
+                    callee: Box::new(expr),
+                    arguments,
+                },
+                start,
+            );
+        }
+
+        Ok(expr)
+    }
+
+    // A comma-separated list of argument expressions, already past the `(`.
+    fn parse_arguments(&mut self) -> Result<Vec<Node>, String> {
+        let mut arguments = Vec::new();
+
+        if !self.check(TokenType::Separator, ")") {
+            loop {
+                arguments.push(self.parse_expression()?);
+                if !self.match_token(TokenType::Separator, ",") {
+                    break;
+                }
+            }
+        }
+
+        Ok(arguments)
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, String> {
+        let start = self.current;
+        if self.match_token(TokenType::Literal, "") {
+            Ok(self.spanned(ASTNode::Literal(self.previous().value.clone()), start))
+        } else if self.match_token(TokenType::Identifier, "") {
+            Ok(self.spanned(ASTNode::Identifier(self.previous().value.clone()), start))
+        } else if self.match_token(TokenType::Separator, "(") {
+            let expr = self.parse_expression()?;
+            self.expect_token(TokenType::Separator, ")")?;
+            Ok(expr)
+        } else {
+            Err(format!("Expected expression at {}", self.location()))
+        }
+    }
+
+    fn match_token(&mut self, token_type: TokenType, value: &str) -> bool {
+        if self.check(token_type.clone(), value) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn check(&self, token_type: TokenType, value: &str) -> bool {
+        if self.is_at_end() {
+            false
+        } else {
+            let token = &self.tokens[self.current];
+            token.token_type == token_type && (value.is_empty() || token.value == value)
+        }
+    }
+
+    fn advance(&mut self) -> &Token {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.tokens.len()
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current - 1]
+    }
+
+    fn expect_token(&mut self, token_type: TokenType, value: &str) -> Result<(), String> {
+        if self.check(token_type.clone(), value) {
+            self.advance();
+            Ok(())
+        } else {
+            Err(format!(
+                "Expected token: {:?} '{}' at {}",
+                token_type,
+                value,
+                self.location()
+            ))
+        }
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, String> {
+        if self.match_token(TokenType::Identifier, "") {
+            Ok(self.previous().value.clone())
+        } else {
+            Err(format!("Expected identifier at {}", self.location()))
+        }
+    }
+}
+
+// Lexer structure
+struct Lexer {
+    input: String,
+    tokens: Vec<Token>,
+    start: usize,
+    current: usize,
+    line: usize,
+    // Character offset at which the current line began, so a token's column can
+    // be reported relative to its line rather than as a global offset.
+    line_start: usize,
+}
+
+impl Lexer {
+    fn new(input: String) -> Self {
+        Lexer {
+            input,
+            tokens: Vec::new(),
+            start: 0,
+            current: 0,
+            line: 1,
+            line_start: 0,
+        }
+    }
+
+    fn tokenize(&mut self) -> Result<Vec<Token>, String> {
+        while !self.is_at_end() {
+            self.start = self.current;
+            self.scan_token()?;
+        }
+
+        self.tokens.push(Token {
+            token_type: TokenType::Separator,
+            value: "EOF".to_string(),
+            line: self.line,
+            column: self.current - self.line_start + 1,
+        });
+
+        Ok(self.tokens.clone())
+    }
+
+    fn scan_token(&mut self) -> Result<(), String> {
+        let c = self.advance();
+        match c {
+            '(' | ')' | '{' | '}' | ',' | ';' | ':' => self.add_token(TokenType::Separator),
+            '+' | '*' => self.add_token(TokenType::Operator),
+            '-' => {
+                // `->` is the single separator token between a parameter list and
+                // a return type; a bare `-` stays the subtraction/negation op.
+                if self.match_char('>') {
+                    self.add_token(TokenType::Separator);
+                } else {
+                    self.add_token(TokenType::Operator);
+                }
+            }
+            // The comparison and assignment operators share a tail: an optional
+            // `=` folded into the same operator token.
+            '=' | '!' | '<' | '>' => {
+                self.match_char('=');
+                self.add_token(TokenType::Operator);
+            }
+            '"' => self.string()?,
+            '0'..='9' => self.number(),
+            'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
+            ' ' | '\r' | '\t' => {}
+            '\n' => {
+                self.line += 1;
+                self.line_start = self.current;
+            }
+            '/' => {
+                if self.match_char('/') {
+                    while self.peek() != '\n' && !self.is_at_end() {
+                        self.advance();
+                    }
+                } else {
+                    self.add_token(TokenType::Operator);
+                }
+            }
+            _ => return Err(format!("Unexpected character: {}", c)),
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self) -> char {
+        let c = self.input.chars().nth(self.current).unwrap();
+        self.current += 1;
+        c
+    }
+
+    fn match_char(&mut self, expected: char) -> bool {
+        if self.is_at_end() {
+            return false;
+        }
+        if self.input.chars().nth(self.current).unwrap() != expected {
+            return false;
+        }
+        self.current += 1;
+        true
+    }
+
+    fn peek(&self) -> char {
+        if self.is_at_end() {
+            '\0'
+        } else {
+            self.input.chars().nth(self.current).unwrap()
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.input.len()
+    }
+
+    fn add_token(&mut self, token_type: TokenType) {
+        let text = &self.input[self.start..self.current];
+        self.tokens.push(Token {
+            token_type,
+            value: text.to_string(),
+            line: self.line,
+            column: self.start - self.line_start + 1,
+        });
+    }
+
+    fn string(&mut self) -> Result<(), String> {
+        while self.peek() != '"' && !self.is_at_end() {
+            if self.peek() == '\n' {
+                self.line += 1;
+                self.line_start = self.current + 1;
+            }
+            self.advance();
+        }
+
+        if self.is_at_end() {
+            return Err("Unterminated string".to_string());
+        }
+
+        self.advance();
+        self.add_token(TokenType::Literal);
+        Ok(())
+    }
+
+    fn number(&mut self) {
+        while self.peek().is_ascii_digit() {
+            self.advance();
+        }
+
+        if self.peek() == '.' && self.input.chars().nth(self.current + 1).unwrap().is_ascii_digit() {
+            self.advance();
+
+            while self.peek().is_ascii_digit() {
+                self.advance();
+            }
+        }
+
+        self.add_token(TokenType::Literal);
+    }
+
+    fn identifier(&mut self) {
+        while self.peek().is_alphanumeric() || self.peek() == '_' {
+            self.advance();
+        }
+
+        let text = &self.input[self.start..self.current];
+        let token_type = match text {
+            "fn" | "let" | "if" | "else" | "while" | "return" | "as" => TokenType::Keyword,
+            _ => TokenType::Identifier,
+        };
+
+        self.add_token(token_type);
+    }
+}
+
+// Which pipeline stage the driver should print.
+enum Stage {
+    Tokens,
+    Ast,
+    Ir,
+    Asm,
+    Run,
+    Symbols,
+}
+
+// How a stage's output should be rendered.
+enum Format {
+    Debug,
+    Json,
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+
+    // Parse the command line: a source file path plus an optional stage flag of
+    // the form `-a` or `-a=Json`. Defaults to dumping the AST in Debug form.
+    let mut file_path: Option<String> = None;
+    let mut stage = Stage::Ast;
+    let mut format = Format::Debug;
+    // When set by `-g[=md5|sha1|sha256]`, the `-s` stage emits source-location
+    // debug info and a source-hash header using the chosen algorithm.
+    let mut debug: Option<HashAlgorithm> = None;
+    // When set by `-c=<path>`, the `-s` stage consults a persistent compile
+    // cache at that path, reusing stored assembly for unchanged source.
+    let mut cache_path: Option<String> = None;
+    // When set by `-q=<path>`, the `-s` stage emits through the dependency-graph
+    // query system persisted at that path, re-emitting only changed functions.
+    let mut query_path: Option<String> = None;
+
+    for arg in &args[1..] {
+        if let Some(flag) = arg.strip_prefix('-') {
+            let (kind, fmt) = match flag.split_once('=') {
+                Some((k, f)) => (k, Some(f)),
+                None => (flag, None),
+            };
+            if kind == "q" {
+                match fmt {
+                    Some(path) => query_path = Some(path.to_string()),
+                    None => {
+                        eprintln!("Flag -q requires a path: -q=<path>");
+                        print_usage(&args[0]);
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+            if kind == "c" {
+                match fmt {
+                    Some(path) => cache_path = Some(path.to_string()),
+                    None => {
+                        eprintln!("Flag -c requires a path: -c=<path>");
+                        print_usage(&args[0]);
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+            if kind == "g" {
+                debug = Some(match fmt {
+                    None | Some("sha256") => HashAlgorithm::Sha256,
+                    Some("sha1") => HashAlgorithm::Sha1,
+                    Some("md5") => HashAlgorithm::Md5,
+                    Some(other) => {
+                        eprintln!("Unknown hash algorithm: {}", other);
+                        print_usage(&args[0]);
+                        return Ok(());
+                    }
+                });
+                continue;
+            }
+            stage = match kind {
+                "t" => Stage::Tokens,
+                "a" => Stage::Ast,
+                "i" => Stage::Ir,
+                "s" => Stage::Asm,
+                "r" => Stage::Run,
+                "y" => Stage::Symbols,
+                other => {
+                    eprintln!("Unknown flag: -{}", other);
+                    print_usage(&args[0]);
+                    return Ok(());
+                }
+            };
+            if let Some(fmt) = fmt {
+                format = match fmt {
+                    "Debug" => Format::Debug,
+                    "Json" => Format::Json,
+                    other => {
+                        eprintln!("Unknown format: {}", other);
+                        print_usage(&args[0]);
+                        return Ok(());
+                    }
+                };
+            }
+        } else {
+            file_path = Some(arg.clone());
+        }
+    }
+
+    let file_path = match file_path {
+        Some(path) => path,
+        None => {
+            print_usage(&args[0]);
+            return Ok(());
+        }
+    };
+
+    // Read input file
+    let file = File::open(&file_path)?;
+    let reader = BufReader::new(file);
+    let mut input = String::new();
+
+    for line in reader.lines() {
+        input.push_str(&line?);
+        input.push('\n');
+    }
+
+    // Keep a copy of the source for the debug emitter's hash record.
+    let source = input.clone();
+
+    // A `.json` input is a serialized AST (as emitted by `-a=Json`); load it
+    // straight back through serde instead of re-lexing. Anything else is D++
+    // source and goes through the lexer and parser.
+    let mut ast = if file_path.ends_with(".json") {
+        match ast_from_json(&source) {
+            Ok(a) => a,
+            Err(e) => {
+                eprintln!("AST load error: {}", e);
+                return Ok(());
+            }
+        }
+    } else {
+        let mut lexer = Lexer::new(input);
+        let tokens = match lexer.tokenize() {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("Lexer error: {}", e);
+                return Ok(());
+            }
+        };
+        eprintln!("Tokenization complete. Found {} tokens.", tokens.len());
+
+        if let Stage::Tokens = stage {
+            match format {
+                Format::Debug => println!("{:#?}", tokens),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&tokens)?),
+            }
+            return Ok(());
+        }
+
+        let mut parser = Parser::new(tokens);
+        match parser.parse() {
+            Ok(a) => a,
+            Err(e) => {
+                eprintln!("Parser error: {}", e);
+                return Ok(());
+            }
+        }
+    };
+    eprintln!("Parsing complete. AST generated.");
+
+    if let Stage::Ast = stage {
+        match format {
+            Format::Debug => println!("{:#?}", ast),
+            Format::Json => println!("{}", ast_to_json(&ast)?),
+        }
+        return Ok(());
+    }
+
+    if let Stage::Symbols = stage {
+        let table = generate_symbol_table(&ast);
+        match format {
+            Format::Debug => println!("{:#?}", table),
+            Format::Json => println!("{}", symbol_table_to_json(&table)?),
+        }
+        return Ok(());
+    }
+
+    // Later stages run the analysis pipeline: type-check against a scoped type
+    // environment, then fold constants before lowering to IR.
+    if let Err(e) = semantic_analysis(&ast) {
+        eprintln!("Semantic error: {}", e);
+        return Ok(());
+    }
+    optimize_ast(&mut ast);
+    let (ir, ir_spans) = generate_ir_with_spans(&ast);
+
+    match stage {
+        Stage::Ir => {
+            for line in &ir {
+                println!("{}", line);
+            }
+        }
+        Stage::Asm => {
+            let asm = if let Some(path) = &query_path {
+                emit_with_query_system(&ast, path)?
+            } else {
+                match debug {
+                Some(hash) => {
+                    let info = DebugInfo {
+                        source_file: &file_path,
+                        source: &source,
+                        hash,
+                    };
+                    generate_target_code_with_debug(&ir, Some(&ir_spans), &info)
+                }
+                None => {
+                    // Consult the persistent cache (if any) around the codegen
+                    // entry point so unchanged source skips re-emission.
+                    let mut cache = match &cache_path {
+                        Some(path) if Path::new(path).exists() => {
+                            CompileCache::load_from_stream(File::open(path)?)?
+                        }
+                        _ => CompileCache::new(),
+                    };
+                    let asm = cache.compile(&source, || generate_target_code(&ir));
+                    if let Some(path) = &cache_path {
+                        cache.save_to_stream(File::create(path)?)?;
+                    }
+                    asm
+                }
+                }
+            };
+            for line in asm {
+                println!("{}", line);
+            }
+        }
+        Stage::Run => {
+            // Interpret the IR directly and report the value left on top of the
+            // operand stack, the program's result.
+            let machine = interpret_ir(&ir);
+            match machine.stack.last() {
+                Some(result) => println!("{}", result),
+                None => println!("(no result)"),
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn print_usage(prog: &str) {
+    eprintln!(
+        "Usage: {} <file> [-t|-a|-i|-s|-r|-y][=Debug|=Json]\n  \
+         -t  dump token stream\n  \
+         -a  dump AST\n  \
+         -i  dump IR\n  \
+         -s  emit target assembly\n  \
+         -r  interpret the IR and print the result\n  \
+         -y  dump the symbol table\n  \
+         -g  with -s, add debug info (-g=md5|sha1|sha256)\n  \
+         -c  with -s, use a compile cache file (-c=<path>)\n  \
+         -q  with -s, use a query-system graph file for incremental re-emission (-q=<path>)",
+        prog
+    );
+}
+
+// Serialize an AST (or any node subtree) to pretty-printed JSON so downstream
+// tools and golden-file tests can consume the parser output.
+fn ast_to_json(ast: &Node) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(ast)
+}
+
+// Reconstruct an AST from JSON produced by `ast_to_json`, letting later stages
+// run from a serialized tree without re-lexing.
+fn ast_from_json(json: &str) -> Result<Node, serde_json::Error> {
+    serde_json::from_str(json)
+}
+
+// Serialize a symbol table to JSON.
+fn symbol_table_to_json(table: &HashMap<String, String>) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(table)
+}
+
+// Helper function to generate symbol table
+fn generate_symbol_table(ast: &Node) -> HashMap<String, String> {
+    let mut symbol_table = HashMap::new();
+
+    fn traverse_ast(node: &Node, table: &mut HashMap<String, String>) {
+        match &node.inner {
+            ASTNode::VariableDeclaration { name, var_type, .. } => {
+                if let ASTNode::Type(type_name) = &var_type.inner {
+                    table.insert(name.clone(), type_name.clone());
+                }
+            }
+            ASTNode::FunctionDeclaration { name, parameters, return_type, .. } => {
+                let mut param_types = Vec::new();
+                for param in parameters {
+                    if let ASTNode::VariableDeclaration { var_type, .. } = &param.inner {
+                        if let ASTNode::Type(type_name) = &var_type.inner {
+                            param_types.push(type_name.clone());
+                        }
+                    }
+                }
+                let ret_type = if let ASTNode::Type(type_name) = &return_type.inner {
+                    type_name.clone()
+                } else {
+                    "void".to_string()
+                };
+                table.insert(name.clone(), format!("fn({}) -> {}", param_types.join(", "), ret_type));
+            }
+            ASTNode::Program(nodes) | ASTNode::Block(nodes) => {
+                for node in nodes {
+                    traverse_ast(node, table);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    traverse_ast(ast, &mut symbol_table);
+    symbol_table
+}
+
+// The type of a value as understood by the semantic pass. Unrecognized type
+// names (user-defined or not-yet-modelled primitives) are carried as `Other`.
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Int,
+    Float,
+    Str,
+    Bool,
+    Other(String),
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Str => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// Map a declared type name onto a `Type`.
+fn type_from_name(name: &str) -> Type {
+    match name {
+        "int" => Type::Int,
+        "float" => Type::Float,
+        "string" => Type::Str,
+        "bool" => Type::Bool,
+        other => Type::Other(other.to_string()),
+    }
+}
+
+// The declared type carried by a `Type` node.
+fn type_of_decl(var_type: &Node) -> Type {
+    if let ASTNode::Type(name) = &var_type.inner {
+        type_from_name(name)
+    } else {
+        Type::Other("void".to_string())
+    }
+}
+
+// Unify the types of a binary operation's operands, allowing int/float mixing
+// and treating unknown types as compatible. `None` means the operands conflict
+// or neither operand's type is known.
+fn unify(a: Option<Type>, b: Option<Type>) -> Option<Type> {
+    match (a, b) {
+        (Some(x), Some(y)) if x == y => Some(x),
+        (Some(x), Some(y)) => match (&x, &y) {
+            (Type::Int, Type::Float) | (Type::Float, Type::Int) => Some(Type::Float),
+            (Type::Other(_), _) | (_, Type::Other(_)) => Some(Type::Other("?".to_string())),
+            _ => None,
+        },
+        (Some(x), None) | (None, Some(x)) => Some(x),
+        (None, None) => None,
+    }
+}
+
+// Whether a value of type `value` may initialize a binding declared as
+// `declared` (exact match, widening int to float, or an unknown target type).
+fn types_compatible(declared: &Type, value: &Type) -> bool {
+    match (declared, value) {
+        (a, b) if a == b => true,
+        (Type::Float, Type::Int) => true,
+        (Type::Other(_), _) => true,
+        _ => false,
+    }
+}
+
+fn is_comparison(op: &str) -> bool {
+    matches!(op, "<" | "<=" | ">" | ">=")
+}
+
+fn is_equality(op: &str) -> bool {
+    matches!(op, "==" | "!=")
+}
+
+// A stack of lexical scopes mapping names to their declared types, plus the
+// arity of every top-level function so call sites can be checked.
+struct Context {
+    scopes: Vec<HashMap<String, Type>>,
+    functions: HashMap<String, usize>,
+}
+
+impl Context {
+    fn new() -> Self {
+        Context {
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: String, ty: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, ty);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Type> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+}
+
+impl ASTNode {
+    // Infer the type of an expression node against the current type environment,
+    // or `None` when it cannot be determined.
+    fn expected_type(&self, ctx: &Context) -> Option<Type> {
+        match self {
+            ASTNode::Literal(value) => Some(infer_literal(value)),
+            ASTNode::Identifier(name) => ctx.lookup(name).cloned(),
+            ASTNode::BinaryOperation { left, operator, right } => {
+                if is_comparison(operator) || is_equality(operator) {
+                    Some(Type::Bool)
+                } else if operator == "=" {
+                    right.inner.expected_type(ctx)
+                } else {
+                    unify(left.inner.expected_type(ctx), right.inner.expected_type(ctx))
+                }
+            }
+            ASTNode::UnaryOperation { operator, operand } => {
+                if operator == "!" {
+                    Some(Type::Bool)
+                } else {
+                    operand.inner.expected_type(ctx)
+                }
+            }
+            ASTNode::Expression(expr) => expr.inner.expected_type(ctx),
+            // A call yields the callee's declared return type.
+            ASTNode::Call { callee, .. } => callee.inner.expected_type(ctx),
+            // A cast produces a value of its target type.
+            ASTNode::Cast { target_type, .. } => Some(type_of_decl(target_type)),
+            _ => None,
+        }
+    }
+}
+
+// Infer a literal's type from its lexeme.
+fn infer_literal(value: &str) -> Type {
+    if value.parse::<i64>().is_ok() {
+        Type::Int
+    } else if value.parse::<f64>().is_ok() {
+        Type::Float
+    } else if value == "true" || value == "false" {
+        Type::Bool
+    } else {
+        Type::Str
+    }
+}
+
+// Helper function to perform semantic analysis
+fn semantic_analysis(ast: &Node) -> Result<(), String> {
+    let mut ctx = Context::new();
+    check_node(ast, &mut ctx)
+}
+
+fn check_node(node: &Node, ctx: &mut Context) -> Result<(), String> {
+    match &node.inner {
+        ASTNode::Program(nodes) => {
+            // Pre-declare top-level functions so forward references resolve.
+            for n in nodes {
+                if let ASTNode::FunctionDeclaration { name, parameters, return_type, .. } = &n.inner {
+                    ctx.declare(name.clone(), type_of_decl(return_type));
+                    ctx.functions.insert(name.clone(), parameters.len());
+                }
+            }
+            for n in nodes {
+                check_node(n, ctx)?;
+            }
+        }
+        ASTNode::FunctionDeclaration { parameters, body, .. } => {
+            ctx.push_scope();
+            for param in parameters {
+                if let ASTNode::VariableDeclaration { name, var_type, .. } = &param.inner {
+                    ctx.declare(name.clone(), type_of_decl(var_type));
+                }
+            }
+            check_node(body, ctx)?;
+            ctx.pop_scope();
+        }
+        ASTNode::Block(nodes) => {
+            ctx.push_scope();
+            for n in nodes {
+                check_node(n, ctx)?;
+            }
+            ctx.pop_scope();
+        }
+        ASTNode::VariableDeclaration { name, var_type, initializer } => {
+            let declared = type_of_decl(var_type);
+            if let Some(init) = initializer {
+                check_node(init, ctx)?;
+                if let Some(init_ty) = init.inner.expected_type(ctx) {
+                    if !types_compatible(&declared, &init_ty) {
+                        return Err(format!(
+                            "Type mismatch: cannot assign {} to '{}' of type {} at line {}, col {}",
+                            init_ty, name, declared, node.span.start_line, node.span.start_col
+                        ));
+                    }
+                }
+            }
+            ctx.declare(name.clone(), declared);
+        }
+        ASTNode::BinaryOperation { left, operator, right } => {
+            check_node(left, ctx)?;
+            check_node(right, ctx)?;
+            if matches!(operator.as_str(), "+" | "-" | "*" | "/") {
+                if let (Some(lt), Some(rt)) =
+                    (left.inner.expected_type(ctx), right.inner.expected_type(ctx))
+                {
+                    if unify(Some(lt.clone()), Some(rt.clone())).is_none() {
+                        return Err(format!(
+                            "Type mismatch: cannot apply '{}' to {} and {} at line {}, col {}",
+                            operator, lt, rt, node.span.start_line, node.span.start_col
+                        ));
+                    }
+                }
+            }
+        }
+        ASTNode::UnaryOperation { operand, .. } => {
+            check_node(operand, ctx)?;
+        }
+        ASTNode::Cast { operand, .. } => {
+            check_node(operand, ctx)?;
+        }
+        ASTNode::Expression(expr) => {
+            check_node(expr, ctx)?;
+        }
+        ASTNode::Call { callee, arguments } => {
+            for arg in arguments {
+                check_node(arg, ctx)?;
+            }
+            if let ASTNode::Identifier(name) = &callee.inner {
+                match ctx.functions.get(name) {
+                    Some(&arity) if arity == arguments.len() => {}
+                    Some(&arity) => {
+                        return Err(format!(
+                            "Wrong number of arguments to '{}': expected {}, found {} at line {}, col {}",
+                            name, arity, arguments.len(), node.span.start_line, node.span.start_col
+                        ));
+                    }
+                    None => {
+                        return Err(format!(
+                            "Undefined function '{}' at line {}, col {}",
+                            name, node.span.start_line, node.span.start_col
+                        ));
+                    }
+                }
+            } else {
+                check_node(callee, ctx)?;
+            }
+        }
+        ASTNode::Identifier(name) if ctx.lookup(name).is_none() => {
+            return Err(format!(
+                "Undefined variable '{}' at line {}, col {}",
+                name, node.span.start_line, node.span.start_col
+            ));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// A literal value interpreted as a number, keeping integers and floats apart so
+// the folded result can be formatted back with the right kind of lexeme.
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn is_zero(&self) -> bool {
+        match self {
+            Num::Int(i) => *i == 0,
+            Num::Float(f) => *f == 0.0,
+        }
+    }
+
+    fn as_f64(&self) -> f64 {
+        match self {
+            Num::Int(i) => *i as f64,
+            Num::Float(f) => *f,
+        }
+    }
+}
+
+// Interpret a literal lexeme as a number, trying integer before float.
+fn literal_as_num(node: &ASTNode) -> Option<Num> {
+    if let ASTNode::Literal(value) = node {
+        if let Ok(i) = value.parse::<i64>() {
+            return Some(Num::Int(i));
+        }
+        if let Ok(f) = value.parse::<f64>() {
+            return Some(Num::Float(f));
+        }
+    }
+    None
+}
+
+// Format a folded float so it keeps a decimal point and stays a float literal.
+fn format_float(f: f64) -> String {
+    let s = format!("{}", f);
+    if s.contains('.') || s.contains('e') || s.contains("inf") || s.contains("NaN") {
+        s
+    } else {
+        format!("{}.0", s)
+    }
+}
+
+// Fold an arithmetic operation on two numeric literals into a new lexeme,
+// preserving integer-ness when both operands are integers. Returns `None` when
+// integer folding would overflow, leaving the node unfolded rather than
+// panicking (the same conservative choice made for divide-by-zero).
+fn fold_arith(op: &str, l: &Num, r: &Num) -> Option<String> {
+    match (l, r) {
+        (Num::Int(a), Num::Int(b)) => {
+            let v = match op {
+                "+" => a.checked_add(*b),
+                "-" => a.checked_sub(*b),
+                "*" => a.checked_mul(*b),
+                "/" => a.checked_div(*b),
+                _ => unreachable!(),
+            }?;
+            Some(v.to_string())
+        }
+        _ => {
+            let (a, b) = (l.as_f64(), r.as_f64());
+            let v = match op {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" => a / b,
+                _ => unreachable!(),
+            };
+            Some(format_float(v))
+        }
+    }
+}
+
+fn fold_compare(op: &str, l: &Num, r: &Num) -> bool {
+    let (a, b) = (l.as_f64(), r.as_f64());
+    match op {
+        "==" => a == b,
+        "!=" => a != b,
+        "<" => a < b,
+        "<=" => a <= b,
+        ">" => a > b,
+        ">=" => a >= b,
+        _ => unreachable!(),
+    }
+}
+
+// Constant-fold a binary operation whose operands are both literals, or return
+// `None` to leave it untouched (non-literal operands, or division by zero).
+fn try_fold_binary(op: &str, left: &ASTNode, right: &ASTNode) -> Option<ASTNode> {
+    let l = literal_as_num(left)?;
+    let r = literal_as_num(right)?;
+    match op {
+        "+" | "-" | "*" | "/" => {
+            if op == "/" && r.is_zero() {
+                return None;
+            }
+            Some(ASTNode::Literal(fold_arith(op, &l, &r)?))
+        }
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => {
+            Some(ASTNode::Literal(fold_compare(op, &l, &r).to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn try_fold_unary(op: &str, operand: &ASTNode) -> Option<ASTNode> {
+    match op {
+        "-" => match literal_as_num(operand)? {
+            Num::Int(i) => Some(ASTNode::Literal((-i).to_string())),
+            Num::Float(f) => Some(ASTNode::Literal(format_float(-f))),
+        },
+        "!" => {
+            if let ASTNode::Literal(value) = operand {
+                match value.as_str() {
+                    "true" => Some(ASTNode::Literal("false".to_string())),
+                    "false" => Some(ASTNode::Literal("true".to_string())),
+                    _ => None,
+                }
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_zero_lit(num: &Option<Num>) -> bool {
+    matches!(num, Some(n) if n.is_zero())
+}
+
+fn is_one_lit(num: &Option<Num>) -> bool {
+    matches!(num, Some(n) if n.as_f64() == 1.0)
+}
+
+// Apply algebraic identities that rewrite a binary operation to one of its
+// operands (or a constant) when the other operand is a neutral/absorbing value.
+fn try_algebraic(op: &str, left: &ASTNode, right: &ASTNode) -> Option<ASTNode> {
+    let left_num = literal_as_num(left);
+    let right_num = literal_as_num(right);
+    match op {
+        "+" => {
+            if is_zero_lit(&right_num) {
+                return Some(left.clone());
+            }
+            if is_zero_lit(&left_num) {
+                return Some(right.clone());
+            }
+        }
+        "-" if is_zero_lit(&right_num) => {
+            return Some(left.clone());
+        }
+        "*" => {
+            if is_zero_lit(&right_num) || is_zero_lit(&left_num) {
+                return Some(ASTNode::Literal("0".to_string()));
+            }
+            if is_one_lit(&right_num) {
+                return Some(left.clone());
+            }
+            if is_one_lit(&left_num) {
+                return Some(right.clone());
+            }
+        }
+        "/" if is_one_lit(&right_num) => {
+            return Some(left.clone());
+        }
+        _ => {}
+    }
+    None
+}
+
+// Helper function to optimize AST
+fn optimize_ast(ast: &mut Node) {
+    // Iterate to a fixpoint so that constants exposed by one rewrite (e.g. an
+    // algebraic identity collapsing a subtree) are folded on the next pass.
+    while optimize_node(ast) {}
+}
+
+// Optimize a node bottom-up, returning whether anything changed.
+fn optimize_node(node: &mut Node) -> bool {
+    let mut changed = false;
+    let mut replacement: Option<ASTNode> = None;
+
+    match &mut node.inner {
+        ASTNode::BinaryOperation { left, operator, right } => {
+            changed |= optimize_node(left);
+            changed |= optimize_node(right);
+            replacement = try_fold_binary(operator, &left.inner, &right.inner)
+                .or_else(|| try_algebraic(operator, &left.inner, &right.inner));
+        }
+        ASTNode::UnaryOperation { operator, operand } => {
+            changed |= optimize_node(operand);
+            replacement = try_fold_unary(operator, &operand.inner);
+        }
+        ASTNode::Cast { operand, .. } => {
+            changed |= optimize_node(operand);
+        }
+        ASTNode::Expression(expr) => {
+            changed |= optimize_node(expr);
+        }
+        ASTNode::VariableDeclaration { initializer: Some(init), .. } => {
+            changed |= optimize_node(init);
+        }
+        ASTNode::Program(nodes) | ASTNode::Block(nodes) => {
+            for node in nodes {
+                changed |= optimize_node(node);
+            }
+        }
+        ASTNode::FunctionDeclaration { body, .. } => {
+            changed |= optimize_node(body);
+        }
+        ASTNode::If { condition, then_branch, else_branch } => {
+            changed |= optimize_node(condition);
+            changed |= optimize_node(then_branch);
+            if let Some(else_branch) = else_branch {
+                changed |= optimize_node(else_branch);
+            }
+        }
+        ASTNode::While { condition, body } => {
+            changed |= optimize_node(condition);
+            changed |= optimize_node(body);
+        }
+        ASTNode::Return(Some(value)) => {
+            changed |= optimize_node(value);
+        }
+        ASTNode::Call { arguments, .. } => {
+            for arg in arguments {
+                changed |= optimize_node(arg);
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(inner) = replacement {
+        node.inner = inner;
+        changed = true;
+    }
+    changed
+}
+
+// Helper function to generate intermediate representation (IR).
+fn generate_ir(ast: &Node) -> Vec<String> {
+    generate_ir_with_spans(ast).0
+}
+
+// Lower the AST to IR while recording, parallel to every emitted instruction,
+// the source span of the node that produced it. The two vectors are always the
+// same length and line up index-for-index, so the debug emitter can turn each
+// instruction into a `.loc` directive. `generate_ir` wraps this and discards
+// the span track for callers that only want the instruction stream.
+fn generate_ir_with_spans(ast: &Node) -> (Vec<String>, Vec<Span>) {
+    let mut ir = Vec::new();
+    let mut spans = Vec::new();
+    // Per-function counter handing out unique label names for control flow. It is
+    // reset at each function boundary and every label carries the function name,
+    // so a function lowered on its own (as the query system does) produces the
+    // same labels it would as part of the whole program.
+    let mut label_id = 0usize;
+
+    fn fresh_label(label_id: &mut usize, prefix: &str, hint: &str) -> String {
+        let label = format!(".L{}_{}_{}", prefix, hint, label_id);
+        *label_id += 1;
+        label
+    }
+
+    // Emit one instruction together with the span it originates from, keeping
+    // the instruction and span tracks aligned.
+    fn emit(ir: &mut Vec<String>, spans: &mut Vec<Span>, span: &Span, line: String) {
+        ir.push(line);
+        spans.push(span.clone());
+    }
+
+    fn generate_node_ir(
+        node: &Node,
+        ir: &mut Vec<String>,
+        spans: &mut Vec<Span>,
+        prefix: &str,
+        label_id: &mut usize,
+    ) {
+        let span = &node.span;
+        match &node.inner {
+            ASTNode::Program(nodes) => {
+                for node in nodes {
+                    generate_node_ir(node, ir, spans, prefix, label_id);
+                }
+            }
+            ASTNode::FunctionDeclaration { name, parameters, body, .. } => {
+                emit(ir, spans, span, format!("function {}:", name));
+                for param in parameters {
+                    if let ASTNode::VariableDeclaration { name, .. } = &param.inner {
+                        emit(ir, spans, &param.span, format!("  param {}", name));
+                    }
+                }
+                let mut fn_label_id = 0usize;
+                generate_node_ir(body, ir, spans, name, &mut fn_label_id);
+                emit(ir, spans, span, "end_function".to_string());
+            }
+            ASTNode::Block(statements) => {
+                for stmt in statements {
+                    generate_node_ir(stmt, ir, spans, prefix, label_id);
+                }
+            }
+            ASTNode::VariableDeclaration { name, initializer: Some(init), .. } => {
+                generate_node_ir(init, ir, spans, prefix, label_id);
+                emit(ir, spans, span, format!("store {}", name));
+            }
+            ASTNode::If { condition, then_branch, else_branch } => {
+                // Evaluate the condition and jump over the then-branch when it is
+                // false; an else-branch (if present) sits past an unconditional
+                // jump that skips it on the taken path.
+                let else_label = fresh_label(label_id, prefix, "else");
+                let end_label = fresh_label(label_id, prefix, "endif");
+                generate_node_ir(condition, ir, spans, prefix, label_id);
+                emit(ir, spans, span, format!("jz {}", else_label));
+                generate_node_ir(then_branch, ir, spans, prefix, label_id);
+                emit(ir, spans, span, format!("jmp {}", end_label));
+                emit(ir, spans, span, format!("label {}", else_label));
+                if let Some(else_branch) = else_branch {
+                    generate_node_ir(else_branch, ir, spans, prefix, label_id);
+                }
+                emit(ir, spans, span, format!("label {}", end_label));
+            }
+            ASTNode::While { condition, body } => {
+                // Re-check the condition at the top of each iteration and fall out
+                // of the loop once it is false.
+                let start_label = fresh_label(label_id, prefix, "while");
+                let end_label = fresh_label(label_id, prefix, "endwhile");
+                emit(ir, spans, span, format!("label {}", start_label));
+                generate_node_ir(condition, ir, spans, prefix, label_id);
+                emit(ir, spans, span, format!("jz {}", end_label));
+                generate_node_ir(body, ir, spans, prefix, label_id);
+                emit(ir, spans, span, format!("jmp {}", start_label));
+                emit(ir, spans, span, format!("label {}", end_label));
+            }
+            ASTNode::Return(value) => {
+                if let Some(value) = value {
+                    generate_node_ir(value, ir, spans, prefix, label_id);
+                }
+                emit(ir, spans, span, "ret".to_string());
+            }
+            ASTNode::BinaryOperation { left, operator, right } => {
+                generate_node_ir(left, ir, spans, prefix, label_id);
+                generate_node_ir(right, ir, spans, prefix, label_id);
+                emit(ir, spans, span, format!("{} {}", operator, operator));
+            }
+            ASTNode::UnaryOperation { operator, operand } => {
+                generate_node_ir(operand, ir, spans, prefix, label_id);
+                emit(ir, spans, span, operator.to_string());
+            }
+            ASTNode::Call { callee, arguments } => {
+                // Push arguments left-to-right, then call through the callee name.
+                for arg in arguments {
+                    generate_node_ir(arg, ir, spans, prefix, label_id);
+                }
+                if let ASTNode::Identifier(name) = &callee.inner {
+                    emit(ir, spans, span, format!("call {}", name));
+                }
+            }
+            ASTNode::Cast { operand, target_type } => {
+                generate_node_ir(operand, ir, spans, prefix, label_id);
+                if let ASTNode::Type(name) = &target_type.inner {
+                    emit(ir, spans, span, format!("cast {}", name));
+                }
+            }
+            ASTNode::Literal(value) => {
+                emit(ir, spans, span, format!("push {}", value));
+            }
+            ASTNode::Identifier(name) => {
+                emit(ir, spans, span, format!("load {}", name));
+            }
+            _ => {}
+        }
+    }
+
+    generate_node_ir(ast, &mut ir, &mut spans, "", &mut label_id);
+    (ir, spans)
+}
+
+// The final machine state left behind after interpreting an IR stream: whatever
+// remains on the operand stack and the values bound to each variable.
+struct Machine {
+    stack: Vec<i64>,
+    vars: HashMap<String, i64>,
+}
+
+// Execute the stack IR directly against an in-memory operand stack and variable
+// map, with no assembler or linker in the loop. This doubles as a reference
+// oracle for differentially testing the emitted assembly against the same
+// program, so it uses the same 64-bit integer arithmetic (including truncating
+// division) that `lower_instruction` emits via `idiv`, except that division by
+// zero yields 0 here where `idiv` would trap. Only the scalar core
+// (`push`/`load`/`store`/`+`/`-`/`*`/`/`) is modelled; control-flow and other
+// instructions are ignored, as are malformed (empty or argless) lines.
+fn interpret_ir(ir: &[String]) -> Machine {
+    let mut machine = Machine {
+        stack: Vec::new(),
+        vars: HashMap::new(),
+    };
+
+    for instruction in ir {
+        let parts: Vec<&str> = instruction.split_whitespace().collect();
+        match parts.first().copied() {
+            Some("push") => {
+                if let Some(value) = parts.get(1).and_then(|v| v.parse::<i64>().ok()) {
+                    machine.stack.push(value);
+                }
+            }
+            Some("load") => {
+                if let Some(name) = parts.get(1) {
+                    let value = machine.vars.get(*name).copied().unwrap_or(0);
+                    machine.stack.push(value);
+                }
+            }
+            Some("store") => {
+                if let Some(name) = parts.get(1) {
+                    if let Some(value) = machine.stack.pop() {
+                        machine.vars.insert(name.to_string(), value);
+                    }
+                }
+            }
+            Some(op @ ("+" | "-" | "*" | "/")) => {
+                let rhs = machine.stack.pop().unwrap_or(0);
+                let lhs = machine.stack.pop().unwrap_or(0);
+                let result = match op {
+                    "+" => lhs + rhs,
+                    "-" => lhs - rhs,
+                    "*" => lhs * rhs,
+                    "/" => {
+                        if rhs == 0 {
+                            0
+                        } else {
+                            lhs / rhs
+                        }
+                    }
+                    _ => unreachable!(),
+                };
+                machine.stack.push(result);
+            }
+            _ => {}
+        }
+    }
+
+    machine
+}
+
+// Helper function to generate target code (e.g., x86 assembly)
+fn generate_target_code(ir: &[String]) -> Vec<String> {
+    let mut asm = Vec::new();
+    // Shadow pointer into an in-memory stack of 4-wide packed floats (`D32x4`).
+    // Each slot is 16 bytes; `vsp` counts the slots currently live so nested
+    // vector expressions lower into non-overlapping `[vstack + n*16]` cells.
+    let mut vsp: usize = 0;
+
+    for instruction in ir {
+        let parts: Vec<&str> = instruction.split_whitespace().collect();
+        lower_instruction(&parts, &mut asm, &mut vsp);
+    }
+
+    asm
+}
+
+// A persistent, source-keyed cache of generated assembly. Compiling a unit
+// whose source is unchanged returns the stored lines instead of re-emitting, so
+// warm rebuilds stay cheap. The whole cache round-trips over any reader/writer
+// in a zlib-compressed form, letting it ship as one compact file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CompileCache {
+    // Keyed by a SHA-256 digest of the unit's source text.
+    entries: HashMap<String, Vec<String>>,
+}
+
+impl CompileCache {
+    fn new() -> Self {
+        CompileCache::default()
+    }
+
+    // Return the cached assembly for `source`, or run `emit` to generate it,
+    // store the result, and return it.
+    fn compile<F>(&mut self, source: &str, emit: F) -> Vec<String>
+    where
+        F: FnOnce() -> Vec<String>,
+    {
+        let key = HashAlgorithm::Sha256.digest(source);
+        if let Some(asm) = self.entries.get(&key) {
+            return asm.clone();
+        }
+        let asm = emit();
+        self.entries.insert(key, asm.clone());
+        asm
+    }
+
+    // Write the cache to `writer` as zlib-compressed JSON.
+    fn save_to_stream<W: Write>(&self, writer: W) -> io::Result<()> {
+        let json = serde_json::to_vec(self)?;
+        let mut encoder = ZlibEncoder::new(writer, Compression::default());
+        encoder.write_all(&json)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    // Read a cache previously written by `save_to_stream`.
+    fn load_from_stream<R: Read>(reader: R) -> io::Result<Self> {
+        let mut decoder = ZlibDecoder::new(reader);
+        let mut json = Vec::new();
+        decoder.read_to_end(&mut json)?;
+        let cache = serde_json::from_slice(&json)?;
+        Ok(cache)
+    }
+}
+
+// A compilation unit fed to the query system: its name, its source text, and
+// the names of the other units it reads (the symbols it references). The reads
+// become the node's dependency edges.
+struct Unit {
+    name: String,
+    source: String,
+    reads: Vec<String>,
+}
+
+// The record kept for one unit between runs: the fingerprint of its own source,
+// the fingerprints of each unit it read during emission, and the assembly it
+// produced. A node is reused on a later run iff every one of these input
+// fingerprints still matches and none of its dependencies were invalidated.
+#[derive(Clone, Serialize, Deserialize)]
+struct QueryNode {
+    source_fingerprint: String,
+    read_edges: HashMap<String, String>,
+    output: Vec<String>,
+}
+
+// Tracks compilation units as a dependency graph so that, when only part of a
+// program changes, only the affected units are re-emitted and the rest reuse
+// their cached assembly. The graph round-trips through JSON so it can persist
+// between compiler runs.
+#[derive(Default, Serialize, Deserialize)]
+struct QuerySystem {
+    nodes: HashMap<String, QueryNode>,
+}
+
+impl QuerySystem {
+    fn new() -> Self {
+        QuerySystem::default()
+    }
+
+    // Load a recorded graph from a JSON file written by a previous run.
+    fn load_from_path(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let system = serde_json::from_reader(file)?;
+        Ok(system)
+    }
+
+    // Persist the current graph as JSON for the next run to reuse.
+    fn save_to_path(&self, path: &str) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+
+    // Re-emit only the units whose inputs changed (or whose transitive
+    // dependencies were re-emitted), reusing cached output for the rest. Returns
+    // the assembly for every unit and updates the recorded graph in place.
+    fn emit<F>(&mut self, units: &[Unit], emit_one: F) -> HashMap<String, Vec<String>>
+    where
+        F: Fn(&Unit) -> Vec<String>,
+    {
+        // Current source fingerprint of every unit in this run.
+        let fingerprints: HashMap<String, String> = units
+            .iter()
+            .map(|u| (u.name.clone(), HashAlgorithm::Sha256.digest(&u.source)))
+            .collect();
+
+        // Seed the invalidation set with units whose own source or whose
+        // recorded read edges no longer match, then propagate to any unit that
+        // reads an already-invalidated one until the set stops growing.
+        let mut invalid: HashMap<String, bool> = HashMap::new();
+        for unit in units {
+            invalid.insert(unit.name.clone(), self.is_dirty(unit, &fingerprints));
+        }
+        loop {
+            let mut changed = false;
+            for unit in units {
+                if invalid[&unit.name] {
+                    continue;
+                }
+                if unit.reads.iter().any(|dep| *invalid.get(dep).unwrap_or(&false)) {
+                    invalid.insert(unit.name.clone(), true);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        // Re-emit the invalidated units and reuse the cached output otherwise.
+        let mut outputs = HashMap::new();
+        for unit in units {
+            let output = if invalid[&unit.name] {
+                let asm = emit_one(unit);
+                let read_edges = unit
+                    .reads
+                    .iter()
+                    .filter_map(|dep| fingerprints.get(dep).map(|fp| (dep.clone(), fp.clone())))
+                    .collect();
+                self.nodes.insert(
+                    unit.name.clone(),
+                    QueryNode {
+                        source_fingerprint: fingerprints[&unit.name].clone(),
+                        read_edges,
+                        output: asm.clone(),
+                    },
+                );
+                asm
+            } else {
+                self.nodes[&unit.name].output.clone()
+            };
+            outputs.insert(unit.name.clone(), output);
+        }
+        outputs
+    }
+
+    // Whether a unit must be re-emitted on its own account: no prior record, a
+    // changed source, or a read edge whose target fingerprint moved.
+    fn is_dirty(&self, unit: &Unit, fingerprints: &HashMap<String, String>) -> bool {
+        let node = match self.nodes.get(&unit.name) {
+            Some(node) => node,
+            None => return true,
+        };
+        if node.source_fingerprint != fingerprints[&unit.name] {
+            return true;
+        }
+        unit.reads.iter().any(|dep| {
+            node.read_edges.get(dep) != fingerprints.get(dep)
+        })
+    }
+}
+
+// Build one compilation unit per top-level function, fingerprinting each by its
+// serialized subtree and recording the names of the functions it calls as its
+// dependency edges.
+fn build_units(ast: &Node) -> Vec<Unit> {
+    let mut units = Vec::new();
+    if let ASTNode::Program(nodes) = &ast.inner {
+        for node in nodes {
+            if let ASTNode::FunctionDeclaration { name, .. } = &node.inner {
+                let source = ast_to_json(node).unwrap_or_default();
+                let mut reads = Vec::new();
+                collect_call_names(node, &mut reads);
+                reads.retain(|r| r != name);
+                units.push(Unit {
+                    name: name.clone(),
+                    source,
+                    reads,
+                });
+            }
+        }
+    }
+    units
+}
+
+// Collect the names of every function called anywhere in a subtree.
+fn collect_call_names(node: &Node, out: &mut Vec<String>) {
+    match &node.inner {
+        ASTNode::Call { callee, arguments } => {
+            if let ASTNode::Identifier(name) = &callee.inner {
+                if !out.contains(name) {
+                    out.push(name.clone());
+                }
+            }
+            collect_call_names(callee, out);
+            for arg in arguments {
+                collect_call_names(arg, out);
+            }
+        }
+        ASTNode::Program(nodes) | ASTNode::Block(nodes) => {
+            for n in nodes {
+                collect_call_names(n, out);
+            }
+        }
+        ASTNode::FunctionDeclaration { parameters, return_type, body, .. } => {
+            for p in parameters {
+                collect_call_names(p, out);
+            }
+            collect_call_names(return_type, out);
+            collect_call_names(body, out);
+        }
+        ASTNode::VariableDeclaration { var_type, initializer, .. } => {
+            collect_call_names(var_type, out);
+            if let Some(init) = initializer {
+                collect_call_names(init, out);
+            }
+        }
+        ASTNode::Expression(inner) => collect_call_names(inner, out),
+        ASTNode::BinaryOperation { left, right, .. } => {
+            collect_call_names(left, out);
+            collect_call_names(right, out);
+        }
+        ASTNode::UnaryOperation { operand, .. } => collect_call_names(operand, out),
+        ASTNode::If { condition, then_branch, else_branch } => {
+            collect_call_names(condition, out);
+            collect_call_names(then_branch, out);
+            if let Some(branch) = else_branch {
+                collect_call_names(branch, out);
+            }
+        }
+        ASTNode::While { condition, body } => {
+            collect_call_names(condition, out);
+            collect_call_names(body, out);
+        }
+        ASTNode::Return(Some(value)) => collect_call_names(value, out),
+        ASTNode::Cast { operand, target_type } => {
+            collect_call_names(operand, out);
+            collect_call_names(target_type, out);
+        }
+        _ => {}
+    }
+}
+
+// Emit assembly through the persistent query system: load the recorded graph
+// from `path` (if present), re-emit only the functions whose source or
+// dependencies changed, persist the updated graph, and concatenate the per-unit
+// assembly back into program order.
+fn emit_with_query_system(ast: &Node, path: &str) -> io::Result<Vec<String>> {
+    let mut system = if Path::new(path).exists() {
+        QuerySystem::load_from_path(path)?
+    } else {
+        QuerySystem::new()
+    };
+
+    let units = build_units(ast);
+    let mut node_map: HashMap<String, &Node> = HashMap::new();
+    if let ASTNode::Program(nodes) = &ast.inner {
+        for node in nodes {
+            if let ASTNode::FunctionDeclaration { name, .. } = &node.inner {
+                node_map.insert(name.clone(), node);
+            }
+        }
+    }
+
+    let outputs = system.emit(&units, |unit| match node_map.get(&unit.name) {
+        Some(func) => generate_target_code(&generate_ir(func)),
+        None => Vec::new(),
+    });
+    system.save_to_path(path)?;
+
+    let mut asm = Vec::new();
+    for unit in &units {
+        if let Some(lines) = outputs.get(&unit.name) {
+            asm.extend(lines.iter().cloned());
+        }
+    }
+    Ok(asm)
+}
+
+// Hash algorithm used to fingerprint the source a translation unit was built
+// from, so a debugger or downstream tool can confirm the emitted code matches.
+#[derive(Debug, Clone, Copy)]
+enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    // The directive name recorded next to the digest in the emitted header.
+    fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    // Hex digest of `source` under this algorithm.
+    fn digest(&self, source: &str) -> String {
+        match self {
+            HashAlgorithm::Md5 => format!("{:x}", Md5::digest(source.as_bytes())),
+            HashAlgorithm::Sha1 => format!("{:x}", Sha1::digest(source.as_bytes())),
+            HashAlgorithm::Sha256 => format!("{:x}", Sha256::digest(source.as_bytes())),
+        }
+    }
+}
+
+// Everything the debug emitter needs to tie generated code back to its source:
+// the file name, its full text (hashed into the header), and the algorithm.
+struct DebugInfo<'a> {
+    source_file: &'a str,
+    source: &'a str,
+    hash: HashAlgorithm,
+}
+
+// Generate assembly like `generate_target_code`, but interleave `.loc`
+// directives mapping each instruction back to its originating source position
+// and prepend a `.file` line plus a `<alg>:<hex>` source-hash record. `spans`
+// is parallel to `ir`; when it is absent (or shorter than `ir`) the `.loc`
+// lines are silently dropped, so the emitter degrades to plain assembly with
+// just the verifiable header.
+fn generate_target_code_with_debug(
+    ir: &[String],
+    spans: Option<&[Span]>,
+    debug: &DebugInfo,
+) -> Vec<String> {
+    let mut asm = Vec::new();
+    let mut vsp: usize = 0;
+
+    // Declare the source as file number 1 so the `.loc` directives below, which
+    // reference that number, resolve to a named file for the assembler.
+    asm.push(format!(".file 1 \"{}\"", debug.source_file));
+    asm.push(format!(
+        ".source_hash {}:{}",
+        debug.hash.name(),
+        debug.hash.digest(debug.source)
+    ));
+
+    for (idx, instruction) in ir.iter().enumerate() {
+        if let Some(span) = spans.and_then(|s| s.get(idx)) {
+            asm.push(format!(".loc 1 {} {}", span.start_line, span.start_col));
+        }
+        let parts: Vec<&str> = instruction.split_whitespace().collect();
+        lower_instruction(&parts, &mut asm, &mut vsp);
+    }
+
+    asm
+}
+
+// Lower a single IR instruction into assembly, threading the packed-float shadow
+// stack pointer `vsp` across calls so both the plain and debug emitters share
+// the exact same lowering.
+fn lower_instruction(parts: &[&str], asm: &mut Vec<String>, vsp: &mut usize) {
+        match parts[0] {
+            "function" => {
+                asm.push(format!("{}:", parts[1].trim_end_matches(':')));
+                asm.push("    push rbp".to_string());
+                asm.push("    mov rbp, rsp".to_string());
+            }
+            "end_function" => {
+                asm.push("    mov rsp, rbp".to_string());
+                asm.push("    pop rbp".to_string());
+                asm.push("    ret".to_string());
+            }
+            "param" => {
+                // Handle parameter passing
+            }
+            "push" => {
+                asm.push(format!("    push {}", parts[1]));
+            }
+            "load" => {
+                asm.push(format!("    mov rax, [{}]", parts[1]));
+                asm.push("    push rax".to_string());
+            }
+            "store" => {
+                asm.push("    pop rax".to_string());
+                asm.push(format!("    mov [{}], rax", parts[1]));
+            }
+            "+" | "-" | "*" | "/" => {
+                asm.push("    pop rbx".to_string());
+                asm.push("    pop rax".to_string());
+                match parts[0] {
+                    "+" => asm.push("    add rax, rbx".to_string()),
+                    "-" => asm.push("    sub rax, rbx".to_string()),
+                    "*" => asm.push("    imul rax, rbx".to_string()),
+                    "/" => {
+                        asm.push("    cqo".to_string());
+                        asm.push("    idiv rbx".to_string());
+                    }
+                    _ => {}
+                }
+                asm.push("    push rax".to_string());
+            }
+            "vpush" => {
+                // Load a packed-float constant/global into the next vstack slot.
+                asm.push(format!("    movaps xmm0, [{}]", parts[1]));
+                asm.push(format!("    movaps [vstack + {}], xmm0", *vsp * 16));
+                *vsp += 1;
+            }
+            "vload" => {
+                asm.push(format!("    movaps xmm0, [{}]", parts[1]));
+                asm.push(format!("    movaps [vstack + {}], xmm0", *vsp * 16));
+                *vsp += 1;
+            }
+            "vstore" => {
+                *vsp = vsp.saturating_sub(1);
+                asm.push(format!("    movaps xmm0, [vstack + {}]", *vsp * 16));
+                asm.push(format!("    movaps [{}], xmm0", parts[1]));
+            }
+            "vadd" | "vmul" if *vsp >= 2 => {
+                // Pop the top two packed floats, combine them with a single SSE
+                // lane-wise instruction, and leave the result on the vstack. Only
+                // lower when two operands are actually live, mirroring the
+                // saturating arithmetic `vstore` uses.
+                let top = *vsp - 1;
+                let next = *vsp - 2;
+                asm.push(format!("    movaps xmm1, [vstack + {}]", top * 16));
+                asm.push(format!("    movaps xmm0, [vstack + {}]", next * 16));
+                match parts[0] {
+                    "vadd" => asm.push("    addps xmm0, xmm1".to_string()),
+                    "vmul" => asm.push("    mulps xmm0, xmm1".to_string()),
+                    _ => {}
+                }
+                asm.push(format!("    movaps [vstack + {}], xmm0", next * 16));
+                *vsp -= 1;
+            }
+            _ => {
+                // Handle other instructions
+            }
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lex and parse a source string, panicking with the stage error on failure.
+    fn parse_source(src: &str) -> Node {
+        let mut lexer = Lexer::new(src.to_string());
+        let tokens = lexer.tokenize().expect("lexing failed");
+        let mut parser = Parser::new(tokens);
+        parser.parse().expect("parsing failed")
+    }
+
+    #[test]
+    fn parses_functions_calls_and_control_flow() {
+        let src = "fn add(a: int, b: int) -> int {\n\
+                   \x20   return a + b;\n\
+                   }\n\
+                   fn main() -> int {\n\
+                   \x20   let x: int = add(1, 2);\n\
+                   \x20   if (x > 0) {\n\
+                   \x20       return x;\n\
+                   \x20   } else {\n\
+                   \x20       return 0;\n\
+                   \x20   }\n\
+                   }\n";
+        let ast = parse_source(src);
+        semantic_analysis(&ast).expect("semantic analysis failed");
+
+        let ir = generate_ir(&ast);
+        assert!(ir.iter().any(|line| line == "call add"));
+        assert!(ir.iter().any(|line| line.starts_with("jz ")));
+        assert!(ir.iter().any(|line| line.starts_with("jmp ")));
+    }
+
+    #[test]
+    fn ast_and_symbol_table_round_trip_through_json() {
+        let src = "fn square(n: int) -> int {\n\
+                   \x20   return n * n;\n\
+                   }\n";
+        let ast = parse_source(src);
+
+        // The AST survives a serialize/deserialize round trip unchanged: the
+        // JSON of the reloaded tree matches the JSON of the original.
+        let json = ast_to_json(&ast).expect("serialize failed");
+        let reloaded = ast_from_json(&json).expect("deserialize failed");
+        assert_eq!(ast_to_json(&reloaded).expect("reserialize failed"), json);
+
+        // The symbol table records each function's signature and serializes to
+        // JSON that parses back to the same map.
+        let table = generate_symbol_table(&ast);
+        assert_eq!(table.get("square").map(String::as_str), Some("fn(int) -> int"));
+        let table_json = symbol_table_to_json(&table).expect("symbol table serialize failed");
+        let reloaded_table: HashMap<String, String> =
+            serde_json::from_str(&table_json).expect("symbol table deserialize failed");
+        assert_eq!(reloaded_table, table);
+    }
+
+    // The value of a `push`/`mov` source operand: a register name or a literal.
+    fn operand(tok: &str, rax: i64, rbx: i64, rdx: i64) -> i64 {
+        match tok {
+            "rax" => rax,
+            "rbx" => rbx,
+            "rdx" => rdx,
+            other => other.parse::<i64>().unwrap_or(0),
+        }
+    }
+
+    // The variable name inside a `[name]` memory reference.
+    fn mem_name(tok: &str) -> &str {
+        tok.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    // A tiny executor for the scalar subset of assembly that `generate_target_code`
+    // emits, used purely to differentially test that lowering against the IR
+    // interpreter. It models rax/rbx/rdx, an operand stack, and a variable memory
+    // map with the same integer semantics as `interpret_ir`.
+    fn simulate_asm(asm: &[String]) -> HashMap<String, i64> {
+        let (mut rax, mut rbx, mut rdx) = (0i64, 0i64, 0i64);
+        let mut stack: Vec<i64> = Vec::new();
+        let mut vars: HashMap<String, i64> = HashMap::new();
+
+        for line in asm {
+            let toks: Vec<String> = line
+                .split_whitespace()
+                .map(|t| t.trim_end_matches(',').to_string())
+                .collect();
+            match toks.first().map(String::as_str) {
+                Some("push") => stack.push(operand(&toks[1], rax, rbx, rdx)),
+                Some("pop") => {
+                    let value = stack.pop().unwrap_or(0);
+                    match toks[1].as_str() {
+                        "rax" => rax = value,
+                        "rbx" => rbx = value,
+                        "rdx" => rdx = value,
+                        _ => {}
+                    }
+                }
+                Some("mov") => {
+                    if toks[1].starts_with('[') {
+                        vars.insert(mem_name(&toks[1]).to_string(), operand(&toks[2], rax, rbx, rdx));
+                    } else if toks[2].starts_with('[') {
+                        let value = vars.get(mem_name(&toks[2])).copied().unwrap_or(0);
+                        if toks[1] == "rax" {
+                            rax = value;
+                        }
+                    }
+                    // register-to-register moves (rbp/rsp bookkeeping) are inert here.
+                }
+                Some("add") => rax += rbx,
+                Some("sub") => rax -= rbx,
+                Some("imul") => rax *= rbx,
+                Some("xor") => rdx = 0,
+                Some("idiv") => {
+                    if rbx != 0 {
+                        rdx = rax % rbx;
+                        rax /= rbx;
+                    } else {
+                        rax = 0;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        vars
+    }
+
+    #[test]
+    fn interpreter_is_an_oracle_for_the_emitted_assembly() {
+        // a = 6 * 7; b = a + 2; c = 84 / a; d = a - 5;
+        let ir = vec![
+            "push 6".to_string(),
+            "push 7".to_string(),
+            "* *".to_string(),
+            "store a".to_string(),
+            "load a".to_string(),
+            "push 2".to_string(),
+            "+ +".to_string(),
+            "store b".to_string(),
+            "push 84".to_string(),
+            "load a".to_string(),
+            "/ /".to_string(),
+            "store c".to_string(),
+            "load a".to_string(),
+            "push 5".to_string(),
+            "- -".to_string(),
+            "store d".to_string(),
+        ];
+
+        let machine = interpret_ir(&ir);
+        let simulated = simulate_asm(&generate_target_code(&ir));
+
+        assert_eq!(machine.vars, simulated);
+        assert_eq!(machine.vars.get("a"), Some(&42));
+        assert_eq!(machine.vars.get("b"), Some(&44));
+        assert_eq!(machine.vars.get("c"), Some(&2));
+        assert_eq!(machine.vars.get("d"), Some(&37));
+    }
+
+    #[test]
+    fn debug_emitter_records_loc_lines_and_selected_hash() {
+        let ir = vec!["push 1".to_string(), "store x".to_string()];
+        let spans = vec![
+            Span { start_line: 1, start_col: 1, end_line: 1, end_col: 2 },
+            Span { start_line: 2, start_col: 3, end_line: 2, end_col: 4 },
+        ];
+        let info = DebugInfo {
+            source_file: "demo.dpp",
+            source: "let x: int = 1;\n",
+            hash: HashAlgorithm::Sha256,
+        };
+
+        let asm = generate_target_code_with_debug(&ir, Some(&spans), &info);
+        assert_eq!(asm[0], ".file 1 \"demo.dpp\"");
+        assert!(asm[1].starts_with(".source_hash sha256:"));
+        assert!(asm.iter().any(|line| line == ".loc 1 1 1"));
+        assert!(asm.iter().any(|line| line == ".loc 1 2 3"));
+
+        // The hash record actually tracks the chosen algorithm.
+        let md5_info = DebugInfo { hash: HashAlgorithm::Md5, ..info };
+        let md5_asm = generate_target_code_with_debug(&ir, None, &md5_info);
+        assert!(md5_asm[1].starts_with(".source_hash md5:"));
+        // With no spans, the `.loc` directives are dropped entirely.
+        assert!(!md5_asm.iter().any(|line| line.starts_with(".loc")));
+    }
+
+    #[test]
+    fn ir_span_track_aligns_and_feeds_loc_directives() {
+        // The span track produced alongside the IR must line up one-for-one
+        // with the instruction stream, so the debug emitter can attach a real
+        // `.loc` directive to every instruction.
+        let ast = parse_source("fn main() -> int {\n\
+                                 \x20   let x: int = 1;\n\
+                                 }\n");
+        let (ir, spans) = generate_ir_with_spans(&ast);
+        assert_eq!(ir.len(), spans.len(), "span track must match IR length");
+        assert!(!ir.is_empty());
+
+        let info = DebugInfo {
+            source_file: "demo.dpp",
+            source: "",
+            hash: HashAlgorithm::Md5,
+        };
+        let asm = generate_target_code_with_debug(&ir, Some(&spans), &info);
+        // The literal `1` sits on line 2, so a matching `.loc` is emitted.
+        assert!(asm.iter().any(|line| line.starts_with(".loc 1 2 ")),
+            "expected a line-2 .loc directive, got {:?}", asm);
+    }
+
+    #[test]
+    fn compile_cache_reuses_unchanged_source_and_round_trips() {
+        use std::cell::Cell;
+
+        let mut cache = CompileCache::new();
+        let calls = Cell::new(0);
+        let emit = || {
+            calls.set(calls.get() + 1);
+            vec!["mov rax, 1".to_string()]
+        };
+
+        let first = cache.compile("fn f() -> int { 1 }", emit);
+        let second = cache.compile("fn f() -> int { 1 }", emit);
+        assert_eq!(first, second);
+        assert_eq!(calls.get(), 1, "unchanged source must not re-emit");
+
+        // Changed source is a cache miss and re-emits.
+        cache.compile("fn f() -> int { 2 }", emit);
+        assert_eq!(calls.get(), 2);
+
+        // Round-trip the whole cache through the zlib-compressed stream form.
+        let mut buffer = Vec::new();
+        cache.save_to_stream(&mut buffer).expect("save failed");
+        let loaded = CompileCache::load_from_stream(&buffer[..]).expect("load failed");
+        assert_eq!(loaded.entries, cache.entries);
+    }
+
+    #[test]
+    fn constant_folding_descends_into_function_bodies() {
+        // Folding must reach expressions inside a function body, not just
+        // top-level nodes: `2 + 3 * 4` should collapse to a single `push 14`,
+        // and arguments threaded through a call (`id(5 + 1)`) fold as well.
+        let mut ast = parse_source("fn id(a: int) -> int {\n\
+                                    \x20   return a;\n\
+                                    }\n\
+                                    fn main() -> int {\n\
+                                    \x20   let x: int = 2 + 3 * 4;\n\
+                                    \x20   return id(5 + 1);\n\
+                                    }\n");
+        optimize_ast(&mut ast);
+        let ir = generate_ir(&ast);
+        assert!(ir.iter().any(|line| line == "push 14"), "expected folded push 14, got {:?}", ir);
+        assert!(!ir.iter().any(|line| line == "push 3"), "operands should have been folded away");
+        assert!(ir.iter().any(|line| line == "push 6"), "call argument 5 + 1 should fold to push 6, got {:?}", ir);
+    }
+
+    #[test]
+    fn constant_folding_leaves_overflowing_arithmetic_unfolded() {
+        // Folding that would overflow must be skipped, not panic. The original
+        // operands survive in the IR.
+        let mut ast = parse_source("fn main() -> int {\n\
+                                    \x20   let x: int = 9223372036854775807 + 1;\n\
+                                    }\n");
+        optimize_ast(&mut ast);
+        let ir = generate_ir(&ast);
+        assert!(ir.iter().any(|line| line == "push 9223372036854775807"));
+    }
+
+    #[test]
+    fn control_flow_labels_are_namespaced_per_function() {
+        // Two functions with their own branches must not share label names, so
+        // the query system can lower each function independently and concatenate
+        // the results without collisions.
+        let src = "fn a(x: int) -> int {\n\
+                   \x20   if (x > 0) { return 1; } else { return 2; }\n\
+                   }\n\
+                   fn b(y: int) -> int {\n\
+                   \x20   if (y > 0) { return 3; } else { return 4; }\n\
+                   }\n";
+        let ast = parse_source(src);
+        let ir = generate_ir(&ast);
+        let labels: Vec<&String> = ir
+            .iter()
+            .filter(|line| line.starts_with("label "))
+            .collect();
+        let unique: std::collections::HashSet<&&String> = labels.iter().collect();
+        assert_eq!(labels.len(), unique.len(), "labels collided across functions");
+        assert!(ir.iter().any(|l| l.contains(".La_")));
+        assert!(ir.iter().any(|l| l.contains(".Lb_")));
+    }
+
+    #[test]
+    fn query_system_reuses_unchanged_units_and_propagates_invalidation() {
+        use std::cell::RefCell;
+
+        // `b` reads `a`; `c` is independent. A second run with identical source
+        // must re-emit nothing.
+        let units = |body_a: &str| {
+            vec![
+                Unit { name: "a".to_string(), source: body_a.to_string(), reads: vec![] },
+                Unit { name: "b".to_string(), source: "call a".to_string(), reads: vec!["a".to_string()] },
+                Unit { name: "c".to_string(), source: "leaf".to_string(), reads: vec![] },
+            ]
+        };
+
+        let emitted = RefCell::new(Vec::new());
+        let emit = |unit: &Unit| {
+            emitted.borrow_mut().push(unit.name.clone());
+            vec![format!("; {}", unit.name)]
+        };
+
+        let mut system = QuerySystem::new();
+        system.emit(&units("v1"), emit);
+        assert_eq!(emitted.borrow().len(), 3, "first run emits every unit");
+
+        emitted.borrow_mut().clear();
+        system.emit(&units("v1"), emit);
+        assert!(emitted.borrow().is_empty(), "unchanged source re-emits nothing");
+
+        // Changing `a` must re-emit `a` and its reader `b`, but not `c`.
+        emitted.borrow_mut().clear();
+        system.emit(&units("v2"), emit);
+        let mut touched = emitted.borrow().clone();
+        touched.sort();
+        assert_eq!(touched, vec!["a".to_string(), "b".to_string()]);
+    }
+}